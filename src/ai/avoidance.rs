@@ -0,0 +1,194 @@
+//! Obstacle avoidance for AI karts.
+//!
+//! An AI kart with [`Avoidance`] casts a fan of rays ahead of it every fixed
+//! step, looking for walls, hazards and other karts. The [`AiDriver`] steers
+//! away from whatever the rays hit, and holds back instead of driving into
+//! a slower kart ahead.
+//!
+//! [`AiDriver`]: super::AiDriver
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use crate::item::hazard::Hazard;
+use crate::kart::{project_on_ground_plane, Chassis};
+
+use super::AiSystem;
+
+/// Avoidance plugin.
+pub struct AvoidancePlugin;
+
+impl Plugin for AvoidancePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            sense_obstacles
+                .in_set(AiSystem::Sense)
+                .before(AiSystem::Drive),
+        );
+    }
+}
+
+/// How high above the kart's origin the rays are cast from.
+const RAY_HEIGHT: f32 = 0.2;
+/// The smallest dot product between a hit normal and up for the hit to count
+/// as ground instead of an obstacle.
+const GROUND_NORMAL_Y: f32 = 0.7;
+
+/// Obstacle sensing for an AI kart.
+#[derive(Clone, Component, Debug)]
+pub struct Avoidance {
+    /// The number of rays in the fan.
+    pub rays: usize,
+    /// The angle the fan covers, in degrees.
+    pub fan_angle: f32,
+    /// How far the rays reach while standing still.
+    pub range: f32,
+    /// How much further the rays reach per m/s of speed.
+    pub speed_range: f32,
+    /// How strongly walls are avoided.
+    pub wall_weight: f32,
+    /// How strongly hazards are avoided.
+    pub hazard_weight: f32,
+    /// How strongly other karts are avoided.
+    pub kart_weight: f32,
+    /// The closest the kart gets to a kart ahead of it.
+    pub min_gap: f32,
+    /// How many seconds of closing speed the kart keeps between it and a
+    /// kart ahead of it.
+    pub headway: f32,
+    steering: f32,
+    hold_back: bool,
+}
+
+impl Avoidance {
+    /// How much to add to the steering to avoid obstacles.
+    pub fn steering(&self) -> f32 {
+        self.steering
+    }
+
+    /// Checks if the kart should stop accelerating so it doesn't drive into
+    /// the kart ahead.
+    pub fn hold_back(&self) -> bool {
+        self.hold_back
+    }
+}
+
+impl Default for Avoidance {
+    fn default() -> Avoidance {
+        Avoidance {
+            rays: 7,
+            fan_angle: 100.,
+            range: 4.,
+            speed_range: 0.4,
+            wall_weight: 1.,
+            hazard_weight: 1.5,
+            kart_weight: 0.8,
+            min_gap: 1.5,
+            headway: 0.5,
+            steering: 0.,
+            hold_back: false,
+        }
+    }
+}
+
+fn sense_obstacles(
+    mut ai_query: Query<(
+        Entity,
+        &mut Avoidance,
+        &GlobalTransform,
+        &Velocity,
+        &Chassis,
+    )>,
+    kart_query: Query<&Velocity, With<Chassis>>,
+    hazard_query: Query<(), With<Hazard>>,
+    sensor_query: Query<(), With<Sensor>>,
+    rapier_context: Res<RapierContext>,
+) {
+    for (kart, mut avoidance, transform, velocity, chassis) in ai_query.iter_mut() {
+        let up = chassis.ground_normal().unwrap_or(Vec3::Y);
+        let forward = project_on_ground_plane(up, transform.forward()).normalize_or_zero();
+        let speed = velocity.linvel.dot(forward);
+
+        let origin = transform.translation() + up * RAY_HEIGHT;
+        let range = avoidance.range + speed.max(0.) * avoidance.speed_range;
+
+        // sensors are only worth seeing if they're hazards, not item boxes
+        let predicate = |e: Entity| !sensor_query.contains(e) || hazard_query.contains(e);
+        let filter = QueryFilter::new()
+            .exclude_rigid_body(kart)
+            .predicate(&predicate);
+
+        let half_fan = avoidance.fan_angle.to_radians() / 2.;
+        let rays = avoidance.rays.max(1);
+
+        let mut danger_left = 0.;
+        let mut danger_right = 0.;
+        let mut danger_ahead = 0.;
+        let mut hold_back = false;
+
+        for i in 0..rays {
+            // from the left edge of the fan to the right, positive is right
+            let angle = if rays == 1 {
+                0.
+            } else {
+                -half_fan + 2. * half_fan * i as f32 / (rays - 1) as f32
+            };
+            let direction = Quat::from_axis_angle(up, -angle) * forward;
+
+            let Some((other, hit)) =
+                rapier_context.cast_ray_and_get_normal(origin, direction, range, true, filter)
+            else {
+                continue;
+            };
+
+            let (weight, other_velocity) = if let Ok(other_velocity) = kart_query.get(other) {
+                (avoidance.kart_weight, Some(other_velocity))
+            } else if hazard_query.contains(other) {
+                (avoidance.hazard_weight, None)
+            } else if hit.normal.dot(up) < GROUND_NORMAL_Y {
+                (avoidance.wall_weight, None)
+            } else {
+                // the ground rising ahead, not an obstacle
+                continue;
+            };
+
+            // closer hits nearer the middle of the fan are more dangerous
+            let closeness = 1. - hit.toi / range;
+            let danger = weight * closeness * (1. - angle.abs() / (half_fan * 2.));
+
+            if angle < 0. {
+                danger_left += danger;
+            } else if angle > 0. {
+                danger_right += danger;
+            } else {
+                danger_ahead += danger;
+            }
+
+            // keep a gap to karts ahead, growing with how fast we're catching
+            // up to them
+            if let Some(other_velocity) = other_velocity {
+                let closing_speed = (velocity.linvel - other_velocity.linvel).dot(direction);
+                let gap = avoidance.min_gap + closing_speed.max(0.) * avoidance.headway;
+
+                if angle.abs() <= half_fan / 2. && hit.toi < gap && closing_speed > -0.5 {
+                    hold_back = true;
+                }
+            }
+        }
+
+        // steer away from each side, and dodge whatever is straight ahead
+        // towards the clearer side
+        let mut steering = danger_left - danger_right;
+
+        if danger_left <= danger_right {
+            steering -= danger_ahead;
+        } else {
+            steering += danger_ahead;
+        }
+
+        avoidance.steering = steering.clamp(-1., 1.);
+        avoidance.hold_back = hold_back;
+    }
+}
@@ -0,0 +1,175 @@
+//! Difficulty presets.
+//!
+//! A [`Difficulty`] sets how well AI karts drive, and how hard they are
+//! rubber-banded to the race. Rubber-banding speeds up AI karts that fall
+//! behind the leader, and slows down an AI kart that leads the local players,
+//! with a [`StatusKind::RubberBand`] effect on their top speed.
+
+use bevy::prelude::*;
+use bevy::utils::thiserror;
+
+use serde::{Deserialize, Serialize};
+
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::kart::status::{Modifier, Stacking, Stat, StatusEffect, StatusEffects, StatusKind};
+use crate::kart::{KartSystem, LocalPlayer};
+use crate::race::{RaceProgress, TrackPath};
+
+use super::{AiDriver, AiSettings, AiSkill};
+
+/// Difficulty plugin.
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, rubber_band_ai_karts.before(KartSystem::Status));
+    }
+}
+
+/// How hard the AI karts are.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash, Serialize)]
+pub enum Difficulty {
+    /// Slow, sloppy AI karts that wait up for the players.
+    Easy,
+    /// AI karts that keep the race close.
+    #[default]
+    Normal,
+    /// Fast, clean AI karts that never wait up.
+    Hard,
+}
+
+impl Difficulty {
+    /// Every difficulty, from easiest to hardest.
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    /// The skill of AI karts at this difficulty.
+    pub fn skill(&self) -> AiSkill {
+        let normal = AiSkill::default();
+
+        match self {
+            Difficulty::Easy => AiSkill {
+                pace: 0.8,
+                cornering: 0.4,
+                steering_gain: 1.5,
+                wander: 1.5,
+                ..normal
+            },
+            Difficulty::Normal => normal,
+            Difficulty::Hard => AiSkill {
+                pace: 1.,
+                cornering: 0.65,
+                steering_gain: 2.5,
+                wander: 0.5,
+                ..normal
+            },
+        }
+    }
+
+    /// The rubber-banding of AI karts at this difficulty.
+    pub fn rubber_band(&self) -> RubberBand {
+        match self {
+            Difficulty::Easy => RubberBand {
+                catch_up: 0.05,
+                slow_down: 0.15,
+                ..default()
+            },
+            Difficulty::Normal => default(),
+            Difficulty::Hard => RubberBand {
+                catch_up: 0.2,
+                slow_down: 0.,
+                ..default()
+            },
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = UnknownDifficulty;
+
+    fn from_str(s: &str) -> Result<Difficulty, UnknownDifficulty> {
+        match s.to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(UnknownDifficulty(s.to_owned())),
+        }
+    }
+}
+
+/// An error for a difficulty name that isn't `easy`, `normal` or `hard`.
+#[derive(Debug, Error)]
+#[error("unknown difficulty {0:?}, expected easy, normal or hard")]
+pub struct UnknownDifficulty(pub String);
+
+/// How AI karts are kept close to the race.
+#[derive(Clone, Debug)]
+pub struct RubberBand {
+    /// How much faster an AI kart gets when far behind the leader.
+    pub catch_up: f32,
+    /// How much slower an AI kart gets when far ahead of the local players.
+    pub slow_down: f32,
+    /// The distance along the track at which the full change applies.
+    pub distance: f32,
+}
+
+impl Default for RubberBand {
+    fn default() -> RubberBand {
+        RubberBand {
+            catch_up: 0.1,
+            slow_down: 0.05,
+            distance: 50.,
+        }
+    }
+}
+
+fn rubber_band_ai_karts(
+    mut ai_query: Query<(&mut StatusEffects, &RaceProgress), With<AiDriver>>,
+    kart_query: Query<(&RaceProgress, Has<LocalPlayer>)>,
+    settings: Res<AiSettings>,
+    track_path: Res<TrackPath>,
+) {
+    if !settings.rubber_banding || track_path.is_empty() {
+        for (mut status_effects, _) in ai_query.iter_mut() {
+            status_effects.remove(StatusKind::RubberBand);
+        }
+
+        return;
+    }
+
+    let rubber_band = settings.difficulty.rubber_band();
+
+    let leader = kart_query
+        .iter()
+        .map(|(progress, _)| progress.total(&track_path))
+        .reduce(f32::max);
+    let best_player = kart_query
+        .iter()
+        .filter(|(_, local)| *local)
+        .map(|(progress, _)| progress.total(&track_path))
+        .reduce(f32::max);
+
+    let Some(leader) = leader else {
+        return;
+    };
+
+    for (mut status_effects, progress) in ai_query.iter_mut() {
+        let total = progress.total(&track_path);
+        let distance = rubber_band.distance.max(f32::EPSILON);
+
+        let behind = ((leader - total) / distance).clamp(0., 1.);
+        let ahead = best_player
+            .map(|best_player| ((total - best_player) / distance).clamp(0., 1.))
+            .unwrap_or(0.);
+
+        let factor = 1. + behind * rubber_band.catch_up - ahead * rubber_band.slow_down;
+
+        status_effects.add(
+            StatusEffect::permanent(StatusKind::RubberBand)
+                .with(Modifier::Multiply(Stat::MaxVelocity, factor))
+                .stacking(Stacking::Replace),
+        );
+    }
+}
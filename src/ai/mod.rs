@@ -0,0 +1,320 @@
+//! CPU opponents.
+//!
+//! An [`AiDriver`] drives its kart by writing [`Commands`](KartCommands) into its
+//! [`PlayerCommands`]. It follows the racing line, authored in the map's glTF
+//! as empty nodes named `racing_line.000`, `racing_line.001`, and so on. Maps
+//! without a racing line are driven along the track path instead.
+//!
+//! AI karts also steer around obstacles, see [`avoidance`].
+
+pub mod avoidance;
+pub mod difficulty;
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+use std::f32::consts::PI;
+
+use crate::kart::input::{Commands as KartCommands, InputSystem, PlayerCommands};
+use crate::kart::status::EffectiveOptions;
+use crate::kart::{project_on_ground_plane, Chassis, KartBundle, PLAYER_START};
+use crate::map::{MapNodes, MapSystem};
+use crate::race::TrackPath;
+use crate::random::Random;
+use crate::GameState;
+
+use avoidance::{Avoidance, AvoidancePlugin};
+use difficulty::{Difficulty, DifficultyPlugin};
+
+/// AI plugin.
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((AvoidancePlugin, DifficultyPlugin))
+            .init_resource::<RacingLine>()
+            .init_resource::<AiSettings>()
+            .add_systems(OnEnter(GameState::InGame), spawn_ai_karts)
+            .add_systems(Update, build_racing_line.after(MapSystem::Nodes))
+            // AI commands are written before anything reads the inputs
+            .configure_sets(FixedUpdate, AiSystem::Drive.before(InputSystem::Collect))
+            .add_systems(FixedUpdate, drive_ai_karts.in_set(AiSystem::Drive));
+    }
+}
+
+/// AI systems.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SystemSet)]
+pub enum AiSystem {
+    /// Senses the obstacles around AI karts.
+    Sense,
+    /// Writes the commands of AI karts.
+    Drive,
+}
+
+/// The prefix of the names of racing line nodes.
+pub const RACING_LINE_PREFIX: &str = "racing_line.";
+
+/// The most AI karts that fit on the starting grid.
+pub const MAX_AI_KARTS: usize = GRID_OFFSETS.len();
+
+/// Where AI karts start, relative to the local player.
+const GRID_OFFSETS: [Vec3; 7] = [
+    Vec3::new(2.5, 0., 0.),
+    Vec3::new(0., 0., 3.5),
+    Vec3::new(2.5, 0., 3.5),
+    Vec3::new(0., 0., 7.),
+    Vec3::new(2.5, 0., 7.),
+    Vec3::new(0., 0., 10.5),
+    Vec3::new(2.5, 0., 10.5),
+];
+
+/// How many AI karts to spawn, and how good they are.
+///
+/// Read when the race starts, so it has to be set before then, usually from
+/// the [race setup](crate::race_setup). Recorded in
+/// [replays](crate::replay).
+#[derive(Clone, Debug, Deserialize, Resource, Serialize)]
+#[serde(default)]
+pub struct AiSettings {
+    /// The number of AI karts.
+    pub count: usize,
+    /// The difficulty the AI karts' skill is based on. Each kart varies a
+    /// little.
+    pub difficulty: Difficulty,
+    /// Whether AI karts are kept close to the race, see
+    /// [`Difficulty::rubber_band`].
+    pub rubber_banding: bool,
+}
+
+impl Default for AiSettings {
+    fn default() -> AiSettings {
+        AiSettings {
+            count: 3,
+            difficulty: default(),
+            rubber_banding: true,
+        }
+    }
+}
+
+/// The racing line AI karts follow.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct RacingLine {
+    path: TrackPath,
+}
+
+impl RacingLine {
+    /// The path of the racing line, falling back to the track path.
+    pub fn path<'a>(&'a self, track_path: &'a TrackPath) -> &'a TrackPath {
+        if self.path.is_empty() {
+            track_path
+        } else {
+            &self.path
+        }
+    }
+}
+
+/// How well an AI drives.
+#[derive(Clone, Debug)]
+pub struct AiSkill {
+    /// The ratio of its top speed the AI drives at on straights.
+    pub pace: f32,
+    /// The ratio of its top speed the AI slows to for the sharpest corners.
+    pub cornering: f32,
+    /// How far ahead along the line the AI steers towards.
+    pub lookahead: f32,
+    /// How much further ahead the AI steers towards per m/s of speed.
+    pub speed_lookahead: f32,
+    /// How far ahead along the line the AI looks for corners to slow for.
+    pub brake_lookahead: f32,
+    /// How hard the AI steers per radian off its target.
+    pub steering_gain: f32,
+    /// How far the AI wanders off the racing line.
+    pub wander: f32,
+    /// How often the AI changes how far it wanders, in seconds.
+    pub wander_interval: f32,
+}
+
+impl Default for AiSkill {
+    fn default() -> AiSkill {
+        AiSkill {
+            pace: 0.95,
+            cornering: 0.5,
+            lookahead: 4.,
+            speed_lookahead: 0.3,
+            brake_lookahead: 10.,
+            steering_gain: 2.,
+            wander: 1.,
+            wander_interval: 2.,
+        }
+    }
+}
+
+/// A kart driven by the computer.
+#[derive(Clone, Component, Debug, Default)]
+pub struct AiDriver {
+    /// How well the kart drives.
+    pub skill: AiSkill,
+    wander: f32,
+    wander_timer: f32,
+    stuck: f32,
+    reversing: f32,
+}
+
+/// How long an AI kart can barely move before backing up, in seconds.
+const STUCK_TIME: f32 = 1.5;
+/// How long an AI kart backs up for when stuck, in seconds.
+const REVERSE_TIME: f32 = 1.;
+/// The speed under which an AI kart might be stuck.
+const STUCK_SPEED: f32 = 1.;
+
+fn spawn_ai_karts(
+    mut commands: Commands,
+    settings: Res<AiSettings>,
+    asset_server: Res<AssetServer>,
+    mut rng: ResMut<Random>,
+) {
+    for offset in GRID_OFFSETS.iter().take(settings.count) {
+        // each kart is a little better or worse than the settings
+        let mut skill = settings.difficulty.skill();
+        skill.pace = (skill.pace * (0.95 + rng.real() * 0.1)).min(1.);
+        skill.cornering *= 0.9 + rng.real() * 0.2;
+        skill.lookahead *= 0.9 + rng.real() * 0.2;
+
+        commands.spawn((
+            KartBundle {
+                transform: Transform::from_translation(PLAYER_START + *offset),
+                def: asset_server.load("karts/default.kart.ron"),
+                ..default()
+            },
+            AiDriver { skill, ..default() },
+            Avoidance::default(),
+        ));
+    }
+}
+
+fn build_racing_line(mut map_nodes: MapNodes, mut racing_line: ResMut<RacingLine>) {
+    if let Some(points) = map_nodes.changed_path(RACING_LINE_PREFIX) {
+        racing_line.path = TrackPath::new(points);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn drive_ai_karts(
+    mut kart_query: Query<(
+        &mut AiDriver,
+        &mut PlayerCommands,
+        &GlobalTransform,
+        &Velocity,
+        &Chassis,
+        &EffectiveOptions,
+        Option<&Avoidance>,
+    )>,
+    racing_line: Res<RacingLine>,
+    track_path: Res<TrackPath>,
+    mut rng: ResMut<Random>,
+    time: Res<Time>,
+) {
+    let path = racing_line.path(&track_path);
+
+    for (mut ai, mut player_commands, transform, velocity, chassis, options, avoidance) in
+        kart_query.iter_mut()
+    {
+        let position = transform.translation();
+
+        let Some(distance) = path.project(position) else {
+            player_commands.set(default());
+            continue;
+        };
+
+        let up = chassis.ground_normal().unwrap_or(Vec3::Y);
+        let forward = project_on_ground_plane(up, transform.forward()).normalize_or_zero();
+        let speed = velocity.linvel.dot(forward);
+
+        // wander around the line a little, so AI karts don't drive in a
+        // single file
+        ai.wander_timer -= time.delta_seconds();
+
+        if ai.wander_timer <= 0. {
+            ai.wander_timer = ai.skill.wander_interval * (0.5 + rng.real());
+            ai.wander = (rng.real() * 2. - 1.) * ai.skill.wander;
+        }
+
+        // steer towards a point ahead on the line
+        let lookahead = ai.skill.lookahead + speed.max(0.) * ai.skill.speed_lookahead;
+        let (Some(target), Some(direction)) = (
+            path.point_at(distance + lookahead),
+            line_direction(path, distance + lookahead),
+        ) else {
+            continue;
+        };
+
+        let side = direction.cross(up).normalize_or_zero();
+        let target = target + side * ai.wander;
+
+        let offset = project_on_ground_plane(up, target - position);
+        let right = forward.cross(up);
+        let angle = f32::atan2(offset.dot(right), offset.dot(forward));
+
+        // slow down for corners ahead
+        let corner = match (
+            line_direction(path, distance),
+            line_direction(path, distance + ai.skill.brake_lookahead),
+        ) {
+            (Some(a), Some(b)) => (a.angle_between(b) / (PI / 2.)).min(1.),
+            _ => 0.,
+        };
+
+        let target_speed =
+            options.max_velocity * ai.skill.pace * (1. - corner * (1. - ai.skill.cornering));
+
+        let mut commands = KartCommands {
+            steering: (angle * ai.skill.steering_gain).clamp(-1., 1.),
+            acceleration: if speed < target_speed {
+                1.
+            } else if speed > target_speed * 1.1 {
+                -1.
+            } else {
+                0.
+            },
+            ..default()
+        };
+
+        if let Some(avoidance) = avoidance {
+            commands.steering = (commands.steering + avoidance.steering()).clamp(-1., 1.);
+
+            if avoidance.hold_back() {
+                commands.acceleration = commands.acceleration.min(0.);
+            }
+        }
+
+        // back up out of walls, steering the other way
+        if ai.reversing > 0. {
+            ai.reversing -= time.delta_seconds();
+
+            commands.acceleration = -1.;
+            commands.steering = -commands.steering;
+        } else if speed.abs() < STUCK_SPEED && commands.acceleration > 0. {
+            ai.stuck += time.delta_seconds();
+
+            if ai.stuck >= STUCK_TIME {
+                ai.stuck = 0.;
+                ai.reversing = REVERSE_TIME;
+            }
+        } else {
+            ai.stuck = 0.;
+        }
+
+        player_commands.set(commands);
+    }
+}
+
+/// The direction of a path at a distance along it.
+fn line_direction(path: &TrackPath, distance: f32) -> Option<Vec3> {
+    let a = path.point_at(distance)?;
+    let b = path.point_at(distance + 1.)?;
+
+    Some((b - a).normalize_or_zero())
+}
@@ -1,41 +1,125 @@
 //! Soochcart camera stuff.
 
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
 use bevy::prelude::*;
 
+use bevy_rapier3d::prelude::*;
+
+use std::f32::consts::PI;
+
+use crate::kart::air::KartLanded;
+use crate::kart::collision::{KartBumped, KartHitWall};
+use crate::kart::input::PlayerCommands;
+use crate::kart::status::{StatusEffects, StatusKind};
+use crate::kart::{project_on_ground_plane, Chassis, KartOptions};
+
 /// A camera plugin.
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, follow_kart_camera.before(CameraSystem::Orbit))
-            .add_systems(Update, orbit_camera.in_set(CameraSystem::Orbit));
+        app.add_systems(
+            Update,
+            (
+                look_back_camera,
+                follow_kart_camera,
+                shake_follow_kart_cameras,
+                free_orbit_camera,
+            )
+                .chain()
+                .in_set(CameraSystem::Follow)
+                .before(CameraSystem::Orbit),
+        )
+        .add_systems(Update, orbit_camera.in_set(CameraSystem::Orbit));
     }
 }
 
 /// Camera systems.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, SystemSet)]
 pub enum CameraSystem {
+    /// Points the orbit of cameras that follow a kart.
+    Follow,
     /// Translates the camera in the orbit component.
     Orbit,
 }
 
+/// How an [`Orbit`] camera is controlled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OrbitMode {
+    /// Follows behind the kart of a [`FollowKart`].
+    #[default]
+    Follow,
+    /// Looks back from in front of the kart of a [`FollowKart`].
+    LookBack,
+    /// Rotated with the mouse or right stick, for debugging.
+    Free,
+}
+
 /// A camera that orbits a subject.
 #[derive(Clone, Component, Debug)]
 pub struct Orbit {
+    /// How the camera is controlled.
+    pub mode: OrbitMode,
     /// The subject to orbit.
     pub subject: Option<Entity>,
     /// The distance to orbit.
     pub distance: f32,
     /// The rotation of the orbit.
     pub rot: Quat,
+    /// The radius of the camera when checking for walls between it and the
+    /// subject.
+    pub collision_radius: f32,
+    /// How quickly the camera moves back out after a wall stops blocking it,
+    /// in 1/s.
+    pub ease_out: f32,
+    /// The largest angle the camera shakes by, in degrees.
+    pub shake_angle: f32,
+    /// How fast the camera shakes, in Hz.
+    pub shake_frequency: f32,
+    /// How much shake wears off per second.
+    pub shake_decay: f32,
+    /// How fast the camera rotates in [`OrbitMode::Free`], in rad per pixel
+    /// of mouse movement.
+    pub free_sensitivity: f32,
+    current_distance: Option<f32>,
+    shake: f32,
+}
+
+impl Orbit {
+    /// The distance the camera is actually at, after being pulled in by
+    /// walls.
+    pub fn current_distance(&self) -> f32 {
+        self.current_distance.unwrap_or(self.distance)
+    }
+
+    /// How much the camera is shaking, from `0` to `1`.
+    pub fn shake(&self) -> f32 {
+        self.shake
+    }
+
+    /// Shakes the camera.
+    ///
+    /// `amount` adds up with any shake left over, to at most `1`.
+    pub fn add_shake(&mut self, amount: f32) {
+        self.shake = (self.shake + amount).clamp(0., 1.);
+    }
 }
 
 impl Default for Orbit {
     fn default() -> Orbit {
         Orbit {
+            mode: default(),
             subject: None,
             distance: 5.,
             rot: Quat::IDENTITY,
+            collision_radius: 0.2,
+            ease_out: 3.,
+            shake_angle: 3.,
+            shake_frequency: 15.,
+            shake_decay: 1.5,
+            free_sensitivity: 0.005,
+            current_distance: None,
+            shake: 0.,
         }
     }
 }
@@ -67,14 +151,41 @@ impl FollowKartBundle {
 pub struct FollowKart {
     /// The kart to follow.
     pub kart: Option<Entity>,
+    /// How quickly the camera turns towards the kart's heading, in 1/s.
+    pub stiffness: f32,
+    /// The furthest the camera can trail behind the kart's heading, in
+    /// degrees.
+    pub max_lag: f32,
+    /// The angle the camera looks down at the kart, in degrees.
+    pub pitch: f32,
+    /// The distance to the kart while standing still.
+    pub distance: f32,
+    /// How much further back the camera is at top speed.
+    pub speed_distance: f32,
+    /// The vertical field of view while standing still, in degrees.
+    pub fov: f32,
+    /// The vertical field of view at top speed, in degrees.
+    pub speed_fov: f32,
+    /// How much the camera shakes per m/s of landing speed.
+    pub landing_shake: f32,
+    /// How much the camera shakes when the kart bumps a kart or wall.
+    pub bump_shake: f32,
+    /// How much the camera shakes when the kart boosts.
+    pub boost_shake: f32,
     yaw: f32,
+    boosting: bool,
 }
 
 impl FollowKart {
+    /// The yaw of the camera, in radians.
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
     /// Returns the orientation of the camera.
     pub fn rotation(&self) -> Quat {
-        Quat::from_axis_angle(Vec3::X, (-15f32).to_radians())
-            * Quat::from_axis_angle(Vec3::Y, self.yaw)
+        Quat::from_axis_angle(Vec3::Y, self.yaw)
+            * Quat::from_axis_angle(Vec3::X, -self.pitch.to_radians())
     }
 }
 
@@ -82,25 +193,203 @@ impl Default for FollowKart {
     fn default() -> FollowKart {
         FollowKart {
             kart: None,
+            stiffness: 6.,
+            max_lag: 60.,
+            pitch: 15.,
+            distance: 5.,
+            speed_distance: 1.,
+            fov: 45.,
+            speed_fov: 60.,
+            landing_shake: 0.05,
+            bump_shake: 0.3,
+            boost_shake: 0.4,
             yaw: 0.,
+            boosting: false,
         }
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn follow_kart_camera(
+    mut camera_query: Query<(&mut Orbit, &mut FollowKart, Option<&mut Projection>)>,
+    kart_query: Query<(
+        &GlobalTransform,
+        Option<&Chassis>,
+        Option<&Velocity>,
+        Option<&KartOptions>,
+    )>,
+    time: Res<Time>,
+) {
+    for (mut orbit, mut follow_kart, projection) in camera_query.iter_mut() {
+        if orbit.mode == OrbitMode::Free {
+            continue;
+        }
+
+        let Some((transform, chassis, velocity, options)) =
+            follow_kart.kart.and_then(|k| kart_query.get(k).ok())
+        else {
+            continue;
+        };
+
+        // widen and pull back with speed
+        let speed = velocity
+            .map(|v| v.linvel.dot(transform.forward()))
+            .unwrap_or(0.);
+        let top_speed = options.map(|o| o.max_velocity).unwrap_or(f32::INFINITY);
+        let t = (speed / top_speed).clamp(0., 1.);
+
+        orbit.distance = follow_kart.distance + follow_kart.speed_distance * t;
+
+        if let Some(mut projection) = projection {
+            if let Projection::Perspective(perspective) = &mut *projection {
+                let fov = follow_kart.fov + (follow_kart.speed_fov - follow_kart.fov) * t;
+
+                perspective.fov = fov.to_radians();
+            }
+        }
+
+        let ground_normal = chassis
+            .and_then(|chassis| chassis.ground_normal())
+            .unwrap_or(Vec3::Y);
+        let heading = project_on_ground_plane(ground_normal, transform.forward());
+
+        // keep the last yaw when the kart points straight up or down
+        if heading.x.abs() + heading.z.abs() > f32::EPSILON {
+            let target = f32::atan2(-heading.x, -heading.z);
+            let error = wrap_angle(target - follow_kart.yaw);
+
+            // ease towards the heading, but never trail too far behind
+            let t = 1. - (-follow_kart.stiffness * time.delta_seconds()).exp();
+            let max_lag = follow_kart.max_lag.to_radians();
+            let lag = (error * (1. - t)).clamp(-max_lag, max_lag);
+
+            follow_kart.yaw = wrap_angle(target - lag);
+        }
+
+        orbit.rot = match orbit.mode {
+            OrbitMode::LookBack => Quat::from_axis_angle(Vec3::Y, PI) * follow_kart.rotation(),
+            _ => follow_kart.rotation(),
+        };
+    }
+}
+
+fn look_back_camera(
     mut camera_query: Query<(&mut Orbit, &FollowKart)>,
-    _kart_query: Query<&GlobalTransform>,
+    kart_query: Query<&PlayerCommands>,
 ) {
     for (mut orbit, follow_kart) in camera_query.iter_mut() {
-        orbit.rot = follow_kart.rotation();
+        if orbit.mode == OrbitMode::Free {
+            continue;
+        }
+
+        let look_back = follow_kart
+            .kart
+            .and_then(|kart| kart_query.get(kart).ok())
+            .is_some_and(|player_commands| player_commands.commands().look_back);
+
+        orbit.mode = if look_back {
+            OrbitMode::LookBack
+        } else {
+            OrbitMode::Follow
+        };
+    }
+}
+
+fn free_orbit_camera(
+    mut camera_query: Query<&mut Orbit>,
+    mut motion_events: EventReader<MouseMotion>,
+    mut wheel_events: EventReader<MouseWheel>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    time: Res<Time>,
+) {
+    let mut motion = motion_events.read().map(|ev| ev.delta).sum::<Vec2>();
+    let scroll = wheel_events
+        .read()
+        .map(|ev| match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            MouseScrollUnit::Pixel => ev.y / 16.,
+        })
+        .sum::<f32>();
+
+    // treat the right stick as a fast mouse
+    for gamepad in gamepads.iter() {
+        let x = axes.get(GamepadAxis::new(gamepad, GamepadAxisType::RightStickX));
+        let y = axes.get(GamepadAxis::new(gamepad, GamepadAxisType::RightStickY));
+
+        motion += Vec2::new(x.unwrap_or(0.), -y.unwrap_or(0.)) * 600. * time.delta_seconds();
+    }
+
+    for mut orbit in camera_query.iter_mut() {
+        if orbit.mode != OrbitMode::Free {
+            continue;
+        }
+
+        let motion = motion * orbit.free_sensitivity;
+
+        orbit.rot = Quat::from_axis_angle(Vec3::Y, -motion.x)
+            * orbit.rot
+            * Quat::from_axis_angle(Vec3::X, -motion.y);
+        orbit.distance = (orbit.distance - scroll).max(1.);
+    }
+}
+
+fn shake_follow_kart_cameras(
+    mut camera_query: Query<(&mut Orbit, &mut FollowKart)>,
+    status_query: Query<&StatusEffects>,
+    mut landed_events: EventReader<KartLanded>,
+    mut bumped_events: EventReader<KartBumped>,
+    mut wall_events: EventReader<KartHitWall>,
+) {
+    let landed = landed_events.read().collect::<Vec<_>>();
+    let bumped = bumped_events
+        .read()
+        .map(|ev| ev.kart)
+        .chain(wall_events.read().map(|ev| ev.kart))
+        .collect::<Vec<_>>();
+
+    for (mut orbit, mut follow_kart) in camera_query.iter_mut() {
+        let Some(kart) = follow_kart.kart else {
+            continue;
+        };
+
+        for ev in landed.iter().filter(|ev| ev.kart == kart) {
+            orbit.add_shake(ev.impact_speed.max(0.) * follow_kart.landing_shake);
+
+            if ev.trick {
+                orbit.add_shake(follow_kart.boost_shake);
+            }
+        }
+
+        if bumped.contains(&kart) {
+            orbit.add_shake(follow_kart.bump_shake);
+        }
+
+        // shake once as a boost starts
+        let boosting = status_query
+            .get(kart)
+            .is_ok_and(|status| status.has(StatusKind::Boost));
+
+        if boosting && !follow_kart.boosting {
+            orbit.add_shake(follow_kart.boost_shake);
+        }
+
+        follow_kart.boosting = boosting;
     }
 }
 
+/// Wraps an angle into `[-PI, PI)`.
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2. * PI) - PI
+}
+
 fn orbit_camera(
-    mut camera_query: Query<(&mut Transform, &Orbit)>,
+    mut camera_query: Query<(&mut Transform, &mut Orbit)>,
     transform_query: Query<&GlobalTransform>,
+    rapier_context: Res<RapierContext>,
+    time: Res<Time>,
 ) {
-    for (mut transform, orbit) in camera_query.iter_mut() {
+    for (mut transform, mut orbit) in camera_query.iter_mut() {
         let Some(subject_transform) = orbit.subject.and_then(|s| transform_query.get(s).ok())
         else {
             continue;
@@ -108,11 +397,52 @@ fn orbit_camera(
 
         let forward = orbit.rot * Vec3::Z;
 
+        // pull the camera in front of anything between it and the subject,
+        // ignoring karts
+        let filter = QueryFilter::exclude_dynamic().exclude_sensors();
+        let blocked = rapier_context
+            .cast_shape(
+                subject_transform.translation(),
+                Quat::IDENTITY,
+                forward,
+                &Collider::ball(orbit.collision_radius),
+                orbit.distance,
+                false,
+                filter,
+            )
+            .map(|(_, toi)| toi.toi)
+            .unwrap_or(orbit.distance);
+
+        let current = orbit.current_distance();
+        let distance = if blocked < current {
+            // snap in so the camera never ends up inside a wall
+            blocked
+        } else {
+            let t = 1. - (-orbit.ease_out * time.delta_seconds()).exp();
+
+            current + (blocked - current) * t
+        };
+
+        orbit.current_distance = Some(distance);
+
+        // shake harder the more shake there is left, with a few out of phase
+        // waves standing in for noise
+        let elapsed = time.elapsed_seconds() * orbit.shake_frequency * 2. * PI;
+        let angle = orbit.shake_angle.to_radians() * orbit.shake * orbit.shake;
+        let shake = Quat::from_euler(
+            EulerRot::YXZ,
+            angle * elapsed.sin(),
+            angle * (elapsed * 1.3 + 1.).sin(),
+            angle * (elapsed * 0.7 + 2.).sin(),
+        );
+
+        orbit.shake = (orbit.shake - orbit.shake_decay * time.delta_seconds()).max(0.);
+
         // start with subject position
         *transform = Transform::from_translation(subject_transform.translation())
             // back camera away
-            * Transform::from_translation(forward * orbit.distance)
+            * Transform::from_translation(forward * distance)
             // face camera in direction
-            * Transform::from_rotation(orbit.rot);
+            * Transform::from_rotation(orbit.rot * shake);
     }
 }
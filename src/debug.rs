@@ -7,7 +7,9 @@ use bevy::transform::TransformSystem;
 
 use bevy_rapier3d::prelude::*;
 
+use crate::camera::{Orbit, OrbitMode};
 use crate::kart::{wheel::Wheel, LocalPlayer};
+use crate::photo::photo_mode_inactive;
 use crate::random::Random;
 
 /// Debug utilities.
@@ -15,10 +17,15 @@ pub struct DebugPlugin;
 
 impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            PostUpdate,
-            (debug_random_impulse, debug_draw_wheels).after(TransformSystem::TransformPropagate),
-        );
+        app.add_systems(Update, debug_free_orbit.run_if(photo_mode_inactive))
+            .add_systems(
+                PostUpdate,
+                (
+                    debug_random_impulse.run_if(photo_mode_inactive),
+                    debug_draw_wheels,
+                )
+                    .after(TransformSystem::TransformPropagate),
+            );
     }
 }
 
@@ -43,6 +50,20 @@ pub fn debug_random_impulse(
     }
 }
 
+/// Switches cameras between following their kart and orbiting freely.
+pub fn debug_free_orbit(mut camera_query: Query<&mut Orbit>, keyboard_input: Res<Input<KeyCode>>) {
+    if !keyboard_input.just_pressed(KeyCode::F3) {
+        return;
+    }
+
+    for mut orbit in camera_query.iter_mut() {
+        orbit.mode = match orbit.mode {
+            OrbitMode::Free => OrbitMode::Follow,
+            _ => OrbitMode::Free,
+        };
+    }
+}
+
 pub fn debug_draw_wheels(
     chassis_query: Query<&GlobalTransform>,
     wheels_query: Query<(&Parent, &Wheel, &GlobalTransform)>,
@@ -54,7 +75,7 @@ pub fn debug_draw_wheels(
         };
 
         let ray_pos = transform.transform_point(wheel.position);
-        let ray_dir = transform.down() * wheel.max_suspension * (1. - wheel.ratio());
+        let ray_dir = transform.down() * wheel.length();
 
         let color = if wheel.ratio() > 0. {
             Color::GREEN
@@ -65,5 +86,15 @@ pub fn debug_draw_wheels(
         gizmos.line(ray_pos, ray_pos + ray_dir, color);
 
         gizmos.sphere(wheel_transform.translation(), Quat::IDENTITY, 0.02, color);
+        gizmos.circle(
+            wheel_transform.translation(),
+            transform.right(),
+            wheel.radius,
+            color,
+        );
+
+        if let Some(contact_point) = wheel.contact_point() {
+            gizmos.sphere(contact_point, Quat::IDENTITY, 0.02, Color::YELLOW);
+        }
     }
 }
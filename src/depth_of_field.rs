@@ -0,0 +1,395 @@
+//! Depth of field.
+//!
+//! A post-processing pass that blurs what is nearer or further than the focus
+//! distance of a camera with a [`DepthOfField`]. The blur reads the depth of
+//! the scene from the camera's [`DepthPrepass`], so the camera needs one too.
+
+use bevy::asset::load_internal_asset;
+use bevy::core_pipeline::core_3d::{self, CORE_3D};
+use bevy::core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state;
+use bevy::core_pipeline::prepass::{DepthPrepass, ViewPrepassTextures};
+use bevy::ecs::query::QueryItem;
+use bevy::prelude::*;
+use bevy::render::extract_component::{
+    ComponentUniforms, DynamicUniformIndex, ExtractComponent, ExtractComponentPlugin,
+    UniformComponentPlugin,
+};
+use bevy::render::render_graph::{
+    NodeRunError, RenderGraphApp, RenderGraphContext, ViewNode, ViewNodeRunner,
+};
+use bevy::render::render_resource::*;
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::texture::BevyDefault;
+use bevy::render::view::{ExtractedView, ViewTarget};
+use bevy::render::{Render, RenderApp, RenderSet};
+
+/// Depth of field plugin.
+pub struct DepthOfFieldPlugin;
+
+impl Plugin for DepthOfFieldPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            DEPTH_OF_FIELD_SHADER_HANDLE,
+            "depth_of_field.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_plugins((
+            ExtractComponentPlugin::<DepthOfField>::default(),
+            UniformComponentPlugin::<DepthOfFieldUniform>::default(),
+        ));
+
+        // WebGL can't read the depth of multisampled views
+        #[cfg(target_arch = "wasm32")]
+        app.add_systems(PostUpdate, disable_msaa_for_depth_of_field);
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<SpecializedRenderPipelines<DepthOfFieldPipeline>>()
+            .add_systems(
+                Render,
+                prepare_depth_of_field_pipelines.in_set(RenderSet::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<DepthOfFieldNode>>(CORE_3D, DEPTH_OF_FIELD)
+            .add_render_graph_edges(
+                CORE_3D,
+                &[
+                    core_3d::graph::node::TONEMAPPING,
+                    DEPTH_OF_FIELD,
+                    core_3d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+                ],
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app.init_resource::<DepthOfFieldPipeline>();
+    }
+}
+
+/// The name of the depth of field node in the 3D render graph.
+pub const DEPTH_OF_FIELD: &str = "depth_of_field";
+
+const DEPTH_OF_FIELD_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x5d2f_1c4e_93a7_4b60_8e1d_27c9_f04a_b3e8);
+
+/// Blurs the view of a camera away from a focus distance.
+///
+/// The camera also needs a [`DepthPrepass`]. Only cameras with a perspective
+/// projection are blurred.
+#[derive(Clone, Component, Debug)]
+pub struct DepthOfField {
+    /// The distance from the camera that is in focus, in meters.
+    pub focus_distance: f32,
+    /// How quickly things blur away from the focus distance.
+    ///
+    /// Far in the background, the blur radius is this fraction of the height
+    /// of the view. `0` turns the blur off.
+    pub aperture: f32,
+    /// The largest blur radius, in pixels.
+    pub max_blur: f32,
+}
+
+impl Default for DepthOfField {
+    fn default() -> DepthOfField {
+        DepthOfField {
+            focus_distance: 10.,
+            aperture: 0.,
+            max_blur: 16.,
+        }
+    }
+}
+
+impl ExtractComponent for DepthOfField {
+    type Query = (&'static DepthOfField, &'static Projection);
+    type Filter = With<DepthPrepass>;
+    type Out = DepthOfFieldUniform;
+
+    fn extract_component(
+        (depth_of_field, projection): QueryItem<'_, Self::Query>,
+    ) -> Option<DepthOfFieldUniform> {
+        let Projection::Perspective(perspective) = projection else {
+            return None;
+        };
+
+        if depth_of_field.aperture <= 0. {
+            return None;
+        }
+
+        Some(DepthOfFieldUniform {
+            focus_distance: depth_of_field.focus_distance.max(perspective.near),
+            aperture: depth_of_field.aperture,
+            max_blur: depth_of_field.max_blur,
+            near: perspective.near,
+        })
+    }
+}
+
+pub use uniform::DepthOfFieldUniform;
+
+// `ShaderType` adds a check for each field that is never called
+#[allow(dead_code)]
+mod uniform {
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    /// The settings of a [`DepthOfField`] as the shader sees them.
+    ///
+    /// [`DepthOfField`]: super::DepthOfField
+    #[derive(Clone, Component, Debug, ShaderType)]
+    pub struct DepthOfFieldUniform {
+        /// The focus distance, kept past the near plane.
+        pub focus_distance: f32,
+        /// The aperture.
+        pub aperture: f32,
+        /// The largest blur radius, in pixels.
+        pub max_blur: f32,
+        /// The near plane of the camera, to turn depth back into distance.
+        pub near: f32,
+    }
+}
+
+#[derive(Resource)]
+struct DepthOfFieldPipeline {
+    layout: BindGroupLayout,
+    multisampled_layout: BindGroupLayout,
+}
+
+impl FromWorld for DepthOfFieldPipeline {
+    fn from_world(world: &mut World) -> DepthOfFieldPipeline {
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = |multisampled: bool| {
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("depth_of_field_bind_group_layout"),
+                entries: &[
+                    // the view
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: true },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    // the depth of the prepass
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Depth,
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: Some(DepthOfFieldUniform::min_size()),
+                        },
+                        count: None,
+                    },
+                ],
+            })
+        };
+
+        DepthOfFieldPipeline {
+            layout: layout(false),
+            multisampled_layout: layout(true),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct DepthOfFieldPipelineKey {
+    texture_format: TextureFormat,
+    multisampled: bool,
+}
+
+impl SpecializedRenderPipeline for DepthOfFieldPipeline {
+    type Key = DepthOfFieldPipelineKey;
+
+    fn specialize(&self, key: DepthOfFieldPipelineKey) -> RenderPipelineDescriptor {
+        let (layout, shader_defs) = if key.multisampled {
+            (
+                self.multisampled_layout.clone(),
+                vec!["MULTISAMPLED".into()],
+            )
+        } else {
+            (self.layout.clone(), Vec::new())
+        };
+
+        RenderPipelineDescriptor {
+            label: Some("depth_of_field_pipeline".into()),
+            layout: vec![layout],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: DEPTH_OF_FIELD_SHADER_HANDLE,
+                shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: key.texture_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: default(),
+            depth_stencil: None,
+            multisample: default(),
+            push_constant_ranges: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Component, Debug)]
+struct ViewDepthOfFieldPipeline {
+    id: CachedRenderPipelineId,
+    multisampled: bool,
+}
+
+#[derive(Default)]
+struct DepthOfFieldNode;
+
+impl ViewNode for DepthOfFieldNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ViewPrepassTextures,
+        &'static ViewDepthOfFieldPipeline,
+        &'static DynamicUniformIndex<DepthOfFieldUniform>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, prepass_textures, view_pipeline, uniform_index): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let depth_of_field_pipeline = world.resource::<DepthOfFieldPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let uniforms = world.resource::<ComponentUniforms<DepthOfFieldUniform>>();
+
+        let (Some(pipeline), Some(depth), Some(uniforms)) = (
+            pipeline_cache.get_render_pipeline(view_pipeline.id),
+            &prepass_textures.depth,
+            uniforms.uniforms().binding(),
+        ) else {
+            return Ok(());
+        };
+
+        // the pipeline is behind for a frame when MSAA changes
+        if (depth.texture.sample_count() > 1) != view_pipeline.multisampled {
+            return Ok(());
+        }
+
+        let layout = if view_pipeline.multisampled {
+            &depth_of_field_pipeline.multisampled_layout
+        } else {
+            &depth_of_field_pipeline.layout
+        };
+
+        let post_process = view_target.post_process_write();
+
+        let sampler = render_context
+            .render_device()
+            .create_sampler(&SamplerDescriptor::default());
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "depth_of_field_bind_group",
+            layout,
+            &BindGroupEntries::sequential((
+                post_process.source,
+                &sampler,
+                &depth.default_view,
+                uniforms,
+            )),
+        );
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("depth_of_field_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: post_process.destination,
+                resolve_target: None,
+                ops: Operations::default(),
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[uniform_index.index()]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+fn prepare_depth_of_field_pipelines(
+    mut commands: Commands,
+    mut pipelines: ResMut<SpecializedRenderPipelines<DepthOfFieldPipeline>>,
+    view_query: Query<(Entity, &ExtractedView), With<DepthOfFieldUniform>>,
+    pipeline_cache: Res<PipelineCache>,
+    depth_of_field_pipeline: Res<DepthOfFieldPipeline>,
+    msaa: Res<Msaa>,
+) {
+    for (entity, view) in view_query.iter() {
+        let texture_format = if view.hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+
+        let multisampled = msaa.samples() > 1;
+
+        let id = pipelines.specialize(
+            &pipeline_cache,
+            &depth_of_field_pipeline,
+            DepthOfFieldPipelineKey {
+                texture_format,
+                multisampled,
+            },
+        );
+
+        commands
+            .entity(entity)
+            .insert(ViewDepthOfFieldPipeline { id, multisampled });
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn disable_msaa_for_depth_of_field(
+    camera_query: Query<(&Camera, &DepthOfField)>,
+    mut msaa: ResMut<Msaa>,
+    mut previous_msaa: Local<Option<Msaa>>,
+) {
+    let blurring = camera_query
+        .iter()
+        .any(|(camera, depth_of_field)| camera.is_active && depth_of_field.aperture > 0.);
+
+    if blurring && previous_msaa.is_none() {
+        *previous_msaa = Some(*msaa);
+        *msaa = Msaa::Off;
+    } else if !blurring {
+        if let Some(previous_msaa) = previous_msaa.take() {
+            *msaa = previous_msaa;
+        }
+    }
+}
@@ -0,0 +1,65 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+struct DepthOfField {
+    focus_distance: f32,
+    aperture: f32,
+    max_blur: f32,
+    near: f32,
+}
+
+@group(0) @binding(0) var screen_texture: texture_2d<f32>;
+@group(0) @binding(1) var screen_sampler: sampler;
+#ifdef MULTISAMPLED
+@group(0) @binding(2) var depth_texture: texture_depth_multisampled_2d;
+#else
+@group(0) @binding(2) var depth_texture: texture_depth_2d;
+#endif
+@group(0) @binding(3) var<uniform> settings: DepthOfField;
+
+const SAMPLES: u32 = 32u;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+// the blur radius at a point of the view, in pixels
+fn blur_radius(uv: vec2<f32>, height: f32) -> f32 {
+    let size = vec2<i32>(textureDimensions(depth_texture));
+    let coords = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2(0), size - 1);
+    let ndc_depth = textureLoad(depth_texture, coords, 0);
+
+    // the projection is reversed and infinite, so 0 is infinitely far away
+    var defocus = 1.0;
+    if ndc_depth > 0.0 {
+        let depth = settings.near / ndc_depth;
+        defocus = abs(depth - settings.focus_distance) / depth;
+    }
+
+    return min(defocus * settings.aperture * height, settings.max_blur);
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(screen_texture));
+    let color = textureSampleLevel(screen_texture, screen_sampler, in.uv, 0.0);
+    let radius = blur_radius(in.uv, size.y);
+
+    if radius < 0.5 {
+        return color;
+    }
+
+    var total = color.rgb;
+    var weight = 1.0;
+
+    // gather a disc of samples, spread evenly along a golden angle spiral
+    for (var i = 0u; i < SAMPLES; i += 1u) {
+        let distance = sqrt((f32(i) + 0.5) / f32(SAMPLES)) * radius;
+        let angle = f32(i) * GOLDEN_ANGLE;
+        let uv = in.uv + vec2(cos(angle), sin(angle)) * distance / size;
+
+        // sharp samples don't spread over their blurry neighbours
+        let sample_weight = clamp(blur_radius(uv, size.y) - distance + 1.0, 0.0, 1.0);
+
+        total += textureSampleLevel(screen_texture, screen_sampler, uv, 0.0).rgb * sample_weight;
+        weight += sample_weight;
+    }
+
+    return vec4(total / weight, color.a);
+}
@@ -0,0 +1,311 @@
+//! Hazard items.
+//!
+//! Hazards are dropped behind a kart and spin out karts that drive over
+//! them. Peels break after one hit, oil slicks stay until they dry up.
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use crate::kart::spin_out::SpinOut;
+use crate::kart::status::StatusEffects;
+use crate::random::Random;
+
+/// Hazard plugin.
+pub struct HazardPlugin;
+
+impl Plugin for HazardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HazardAssets>()
+            .add_systems(Update, (hit_hazards, expire_hazards).chain());
+    }
+}
+
+/// How far behind a kart hazards are dropped.
+const HAZARD_OFFSET: f32 = 1.2;
+/// How far below the drop point the ground is looked for.
+const HAZARD_DROP_HEIGHT: f32 = 3.;
+/// How long the kart that dropped a hazard can't hit it, in seconds.
+const OWNER_GRACE: f32 = 1.;
+/// How long a kart spins out after hitting a peel, in seconds.
+const PEEL_SPIN_OUT: f32 = 0.8;
+/// How long a peel lasts, in seconds.
+const PEEL_LIFETIME: f32 = 30.;
+/// How long a kart spins out after hitting an oil slick, in seconds.
+const OIL_SLICK_SPIN_OUT: f32 = 1.2;
+/// How long an oil slick lasts, in seconds.
+const OIL_SLICK_LIFETIME: f32 = 12.;
+/// The radius of an oil slick.
+const OIL_SLICK_RADIUS: f32 = 1.2;
+
+/// A hazard on the track.
+#[derive(Clone, Component, Debug)]
+pub struct Hazard {
+    /// What kind of hazard it is.
+    pub kind: HazardKind,
+    /// The kart that dropped the hazard.
+    pub owner: Entity,
+    age: f32,
+}
+
+impl Hazard {
+    /// Creates a new hazard.
+    pub fn new(kind: HazardKind, owner: Entity) -> Hazard {
+        Hazard {
+            kind,
+            owner,
+            age: 0.,
+        }
+    }
+}
+
+/// The kind of a hazard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HazardKind {
+    /// Spins out the first kart to hit it, then breaks.
+    Peel,
+    /// Spins out every kart that drives through it.
+    OilSlick,
+}
+
+impl HazardKind {
+    /// How long a kart spins out after hitting the hazard, in seconds.
+    pub fn spin_out(&self) -> f32 {
+        match self {
+            HazardKind::Peel => PEEL_SPIN_OUT,
+            HazardKind::OilSlick => OIL_SLICK_SPIN_OUT,
+        }
+    }
+
+    /// How long the hazard lasts, in seconds.
+    pub fn lifetime(&self) -> f32 {
+        match self {
+            HazardKind::Peel => PEEL_LIFETIME,
+            HazardKind::OilSlick => OIL_SLICK_LIFETIME,
+        }
+    }
+
+    fn collider(&self) -> Collider {
+        match self {
+            HazardKind::Peel => Collider::cuboid(0.2, 0.1, 0.2),
+            HazardKind::OilSlick => Collider::cylinder(0.1, OIL_SLICK_RADIUS),
+        }
+    }
+}
+
+/// A bundle for a hazard.
+#[derive(Bundle)]
+pub struct HazardBundle {
+    pub pbr: PbrBundle,
+    pub hazard: Hazard,
+    pub collider: Collider,
+    pub sensor: Sensor,
+}
+
+impl HazardBundle {
+    /// Creates a new hazard at a position.
+    pub fn new(hazard: Hazard, position: Vec3, assets: &HazardAssets) -> HazardBundle {
+        let (mesh, material) = match hazard.kind {
+            HazardKind::Peel => (assets.peel_mesh.clone(), assets.peel_material.clone()),
+            HazardKind::OilSlick => (
+                assets.oil_slick_mesh.clone(),
+                assets.oil_slick_material.clone(),
+            ),
+        };
+
+        HazardBundle {
+            pbr: PbrBundle {
+                mesh,
+                material,
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+            collider: hazard.kind.collider(),
+            hazard,
+            sensor: Sensor,
+        }
+    }
+}
+
+/// The meshes and materials of hazards.
+#[derive(Debug, Resource)]
+pub struct HazardAssets {
+    peel_mesh: Handle<Mesh>,
+    peel_material: Handle<StandardMaterial>,
+    oil_slick_mesh: Handle<Mesh>,
+    oil_slick_material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for HazardAssets {
+    fn from_world(world: &mut World) -> HazardAssets {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let peel_mesh = meshes.add(shape::Box::new(0.4, 0.1, 0.4).into());
+        let oil_slick_mesh = meshes.add(
+            shape::Cylinder {
+                radius: OIL_SLICK_RADIUS,
+                height: 0.02,
+                ..default()
+            }
+            .into(),
+        );
+
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let peel_material = materials.add(Color::YELLOW.into());
+        let oil_slick_material = materials.add(StandardMaterial {
+            base_color: Color::rgb(0.05, 0.05, 0.08),
+            perceptual_roughness: 0.1,
+            ..default()
+        });
+
+        HazardAssets {
+            peel_mesh,
+            peel_material,
+            oil_slick_mesh,
+            oil_slick_material,
+        }
+    }
+}
+
+/// Finds where a hazard dropped by a kart lands.
+///
+/// Returns the point behind the kart if there is no ground below it.
+pub fn drop_position(
+    kart: Entity,
+    transform: &GlobalTransform,
+    rapier_context: &RapierContext,
+) -> Vec3 {
+    let position = transform.translation() + transform.back() * HAZARD_OFFSET;
+    let filter = QueryFilter::new()
+        .exclude_sensors()
+        .exclude_rigid_body(kart);
+
+    rapier_context
+        .cast_ray(position, Vec3::NEG_Y, HAZARD_DROP_HEIGHT, true, filter)
+        .map(|(_, toi)| position + Vec3::NEG_Y * toi)
+        .unwrap_or(position)
+}
+
+fn hit_hazards(
+    mut commands: Commands,
+    hazard_query: Query<&Hazard>,
+    mut kart_query: Query<(&mut SpinOut, &mut StatusEffects, &mut Velocity)>,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut rng: ResMut<Random>,
+) {
+    for ev in collision_events.read() {
+        let CollisionEvent::Started(e1, e2, _) = *ev else {
+            continue;
+        };
+
+        let (hazard_entity, kart) = if hazard_query.contains(e1) {
+            (e1, e2)
+        } else {
+            (e2, e1)
+        };
+
+        let Ok(hazard) = hazard_query.get(hazard_entity) else {
+            continue;
+        };
+
+        if kart == hazard.owner && hazard.age < OWNER_GRACE {
+            continue;
+        }
+
+        let Ok((mut spin_out, mut status_effects, mut velocity)) = kart_query.get_mut(kart) else {
+            continue;
+        };
+
+        // peels break even on invulnerable karts
+        spin_out.start(
+            &mut status_effects,
+            &mut velocity,
+            hazard.kind.spin_out(),
+            &mut rng,
+        );
+
+        if hazard.kind == HazardKind::Peel {
+            commands.entity(hazard_entity).despawn_recursive();
+        }
+    }
+}
+
+fn expire_hazards(
+    mut commands: Commands,
+    mut hazard_query: Query<(Entity, &mut Hazard)>,
+    time: Res<Time>,
+) {
+    for (entity, mut hazard) in hazard_query.iter_mut() {
+        hazard.age += time.delta_seconds();
+
+        if hazard.age > hazard.kind.lifetime() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use bevy::scene::ScenePlugin;
+
+    use bevy_rapier3d::prelude::*;
+
+    use crate::kart::wheel::{Wheel, WheelPlugin};
+
+    use super::HazardKind;
+
+    #[test]
+    fn wheels_drive_over_peels() {
+        let mut app = App::new();
+
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            AssetPlugin::default(),
+            ScenePlugin,
+            RapierPhysicsPlugin::<NoUserData>::default(),
+            WheelPlugin,
+        ))
+        .init_asset::<Mesh>();
+
+        // the top of the ground is at zero, with a peel lying on it
+        app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0., -0.5, 0.)),
+            Collider::cuboid(5., 0.5, 5.),
+        ));
+        app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0., 0.1, 0.)),
+            HazardKind::Peel.collider(),
+            Sensor,
+        ));
+
+        let chassis = app
+            .world
+            .spawn(TransformBundle::from_transform(Transform::from_xyz(
+                0., 0.4, 0.,
+            )))
+            .id();
+        let wheel = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                Wheel::new(Vec3::ZERO, Wheel::default().max_suspension),
+            ))
+            .set_parent(chassis)
+            .id();
+
+        app.update();
+        app.world.run_schedule(FixedUpdate);
+
+        let wheel = app.world.get::<Wheel>(wheel).unwrap();
+        let contact_point = wheel
+            .contact_point()
+            .expect("the wheel should reach the ground");
+
+        assert!(
+            contact_point.y.abs() < 0.01,
+            "the wheel should touch the ground under the peel, not the peel, but touched {contact_point}",
+        );
+    }
+}
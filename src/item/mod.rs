@@ -0,0 +1,383 @@
+//! Items.
+//!
+//! Item boxes are placed in the map's glTF as empty nodes whose names start
+//! with `item_box`. Driving through one gives the kart an item, rolled from
+//! the [`ItemTable`] based on its [`RacePosition`]. Boxes come back after a
+//! short delay.
+
+pub mod hazard;
+pub mod projectile;
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use crate::kart::input::{InputSystem, PlayerCommands};
+use crate::kart::spin_out::SpinOut;
+use crate::kart::status::{Modifier, Stat, StatusEffect, StatusEffects, StatusKind};
+use crate::kart::Chassis;
+use crate::map::{MapNodes, MapSystem};
+use crate::race::RacePosition;
+use crate::random::Random;
+
+use hazard::{drop_position, Hazard, HazardAssets, HazardBundle, HazardKind, HazardPlugin};
+use projectile::{
+    Projectile, ProjectileAssets, ProjectileBundle, ProjectileKind, ProjectilePlugin,
+};
+
+/// Item plugin.
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((ProjectilePlugin, HazardPlugin))
+            .init_resource::<ItemTable>()
+            .init_resource::<ItemBoxAssets>()
+            .add_systems(Update, add_item_boxes.after(MapSystem::Nodes))
+            .add_systems(Update, (collect_item_boxes, respawn_item_boxes).chain())
+            .add_systems(
+                FixedUpdate,
+                use_items
+                    .in_set(ItemSystem::Use)
+                    .after(InputSystem::Collect),
+            );
+    }
+}
+
+/// Item systems.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SystemSet)]
+pub enum ItemSystem {
+    /// Uses the items karts are holding.
+    Use,
+}
+
+/// The prefix of the names of item box nodes.
+pub const ITEM_BOX_PREFIX: &str = "item_box";
+
+/// How long a boost item lasts, in seconds.
+const BOOST_DURATION: f32 = 1.5;
+/// How much a boost item adds to the top speed.
+const BOOST_SPEED: f32 = 6.;
+/// How fast shells are fired, relative to the kart firing them.
+const SHELL_SPEED: f32 = 20.;
+/// How many walls a shell can bounce off.
+const SHELL_BOUNCES: u32 = 3;
+/// How far ahead of a kart shells are fired from.
+const SHELL_OFFSET: f32 = 1.;
+
+/// An item a kart can hold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum Item {
+    /// Raises the top speed of the kart for a moment.
+    Boost,
+    /// Fires a shell straight ahead, bouncing off walls.
+    Shell,
+    /// Fires a shell that follows the track to the kart ahead.
+    HomingShell,
+    /// Drops a peel behind the kart.
+    Peel,
+    /// Drops an oil slick behind the kart.
+    OilSlick,
+}
+
+/// The item a kart is holding.
+#[derive(Clone, Component, Debug, Default)]
+pub struct HeldItem {
+    /// The item, if the kart has one.
+    pub item: Option<Item>,
+}
+
+/// An item box.
+#[derive(Clone, Component, Debug)]
+pub struct ItemBox {
+    /// How long the box takes to come back after it is collected, in seconds.
+    pub respawn_delay: f32,
+    respawn: f32,
+}
+
+impl ItemBox {
+    /// Checks if the box can be collected.
+    pub fn available(&self) -> bool {
+        self.respawn <= 0.
+    }
+}
+
+impl Default for ItemBox {
+    fn default() -> ItemBox {
+        ItemBox {
+            respawn_delay: 3.,
+            respawn: 0.,
+        }
+    }
+}
+
+/// The odds of rolling each item.
+///
+/// The weight of each item is interpolated between its weight for the leader
+/// and its weight for the kart in last place.
+#[derive(Clone, Debug, Resource)]
+pub struct ItemTable {
+    /// The items that can be rolled.
+    pub entries: Vec<ItemOdds>,
+}
+
+impl ItemTable {
+    /// Rolls an item for a kart at `position` out of `karts` karts.
+    pub fn roll(&self, position: usize, karts: usize, rng: &mut Random) -> Option<Item> {
+        let t = if karts > 1 {
+            (position.saturating_sub(1) as f32 / (karts - 1) as f32).clamp(0., 1.)
+        } else {
+            0.
+        };
+
+        let weights = self
+            .entries
+            .iter()
+            .map(|odds| odds.first + (odds.last - odds.first) * t)
+            .collect::<Vec<_>>();
+
+        rng.weighted(&weights).map(|i| self.entries[i].item)
+    }
+}
+
+impl Default for ItemTable {
+    fn default() -> ItemTable {
+        ItemTable {
+            entries: vec![
+                ItemOdds {
+                    item: Item::Boost,
+                    first: 1.,
+                    last: 2.,
+                },
+                ItemOdds {
+                    item: Item::Shell,
+                    first: 2.,
+                    last: 1.,
+                },
+                ItemOdds {
+                    item: Item::HomingShell,
+                    first: 0.,
+                    last: 1.,
+                },
+                ItemOdds {
+                    item: Item::Peel,
+                    first: 2.,
+                    last: 0.5,
+                },
+                ItemOdds {
+                    item: Item::OilSlick,
+                    first: 1.,
+                    last: 0.5,
+                },
+            ],
+        }
+    }
+}
+
+/// The odds of rolling an item.
+#[derive(Clone, Debug)]
+pub struct ItemOdds {
+    /// The item.
+    pub item: Item,
+    /// The weight of the item for the leader.
+    pub first: f32,
+    /// The weight of the item for the kart in last place.
+    pub last: f32,
+}
+
+/// The mesh and material of item boxes.
+#[derive(Debug, Resource)]
+pub struct ItemBoxAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for ItemBoxAssets {
+    fn from_world(world: &mut World) -> ItemBoxAssets {
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(shape::Cube { size: 0.6 }.into());
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: Color::rgba(1., 0.8, 0.2, 0.7),
+                alpha_mode: AlphaMode::Blend,
+                ..default()
+            });
+
+        ItemBoxAssets { mesh, material }
+    }
+}
+
+fn add_item_boxes(
+    mut commands: Commands,
+    map_nodes: MapNodes,
+    item_box_assets: Res<ItemBoxAssets>,
+) {
+    for entity in map_nodes.added(ITEM_BOX_PREFIX) {
+        commands
+            .entity(entity)
+            .insert((ItemBox::default(), Collider::cuboid(0.4, 0.4, 0.4), Sensor))
+            .with_children(|parent| {
+                parent.spawn(PbrBundle {
+                    mesh: item_box_assets.mesh.clone(),
+                    material: item_box_assets.material.clone(),
+                    ..default()
+                });
+            });
+    }
+}
+
+fn collect_item_boxes(
+    mut commands: Commands,
+    mut item_box_query: Query<(&mut ItemBox, &mut Visibility)>,
+    mut kart_query: Query<(&mut HeldItem, &RacePosition), With<Chassis>>,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut rng: ResMut<Random>,
+    item_table: Res<ItemTable>,
+) {
+    let karts = kart_query.iter().count();
+
+    for ev in collision_events.read() {
+        let CollisionEvent::Started(e1, e2, _) = *ev else {
+            continue;
+        };
+
+        let (item_box, kart) = if item_box_query.contains(e1) {
+            (e1, e2)
+        } else {
+            (e2, e1)
+        };
+
+        let Ok((mut held_item, position)) = kart_query.get_mut(kart) else {
+            continue;
+        };
+
+        let Ok((mut item_box_state, mut visibility)) = item_box_query.get_mut(item_box) else {
+            continue;
+        };
+
+        if !item_box_state.available() {
+            continue;
+        }
+
+        // break the box even if the kart already has an item
+        item_box_state.respawn = item_box_state.respawn_delay;
+        *visibility = Visibility::Hidden;
+        commands.entity(item_box).insert(ColliderDisabled);
+
+        if held_item.item.is_none() {
+            held_item.item = item_table.roll(position.0, karts, &mut rng);
+        }
+    }
+}
+
+fn respawn_item_boxes(
+    mut commands: Commands,
+    mut item_box_query: Query<(Entity, &mut ItemBox, &mut Visibility)>,
+    time: Res<Time>,
+) {
+    for (entity, mut item_box, mut visibility) in item_box_query.iter_mut() {
+        if item_box.available() {
+            continue;
+        }
+
+        item_box.respawn -= time.delta_seconds();
+
+        if item_box.available() {
+            *visibility = Visibility::Inherited;
+            commands.entity(entity).remove::<ColliderDisabled>();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn use_items(
+    mut commands: Commands,
+    mut kart_query: Query<(
+        Entity,
+        &mut HeldItem,
+        &mut PlayerCommands,
+        &mut StatusEffects,
+        &Chassis,
+        &SpinOut,
+        &GlobalTransform,
+        &Velocity,
+        &RacePosition,
+    )>,
+    projectile_assets: Res<ProjectileAssets>,
+    hazard_assets: Res<HazardAssets>,
+    rapier_context: Res<RapierContext>,
+) {
+    // the kart in each position, to find the kart ahead
+    let positions = kart_query
+        .iter()
+        .map(|(kart, _, _, _, _, _, _, _, position)| (position.0, kart))
+        .collect::<Vec<_>>();
+
+    for (
+        kart,
+        mut held_item,
+        mut player_commands,
+        mut status_effects,
+        chassis,
+        spin_out,
+        transform,
+        velocity,
+        position,
+    ) in kart_query.iter_mut()
+    {
+        if !player_commands.take_use_item() || chassis.stunned() || spin_out.spinning() {
+            continue;
+        }
+
+        let Some(item) = held_item.item.take() else {
+            continue;
+        };
+
+        match item {
+            Item::Boost => {
+                status_effects.add(
+                    StatusEffect::new(StatusKind::Boost, BOOST_DURATION)
+                        .with(Modifier::Add(Stat::MaxVelocity, BOOST_SPEED)),
+                );
+            }
+            Item::Shell | Item::HomingShell => {
+                let forward = transform.forward();
+                let speed = velocity.linvel.dot(forward).max(0.) + SHELL_SPEED;
+
+                let kind = if item == Item::Shell {
+                    ProjectileKind::Bouncing {
+                        bounces: SHELL_BOUNCES,
+                    }
+                } else {
+                    let target = positions
+                        .iter()
+                        .find(|(p, _)| *p + 1 == position.0)
+                        .map(|(_, kart)| *kart);
+
+                    ProjectileKind::Homing { target }
+                };
+
+                commands.spawn(ProjectileBundle::new(
+                    Projectile::new(kind, forward * speed, kart),
+                    transform.translation() + forward * SHELL_OFFSET,
+                    &projectile_assets,
+                ));
+            }
+            Item::Peel | Item::OilSlick => {
+                let kind = if item == Item::Peel {
+                    HazardKind::Peel
+                } else {
+                    HazardKind::OilSlick
+                };
+
+                commands.spawn(HazardBundle::new(
+                    Hazard::new(kind, kart),
+                    drop_position(kart, transform, &rapier_context),
+                    &hazard_assets,
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Projectile items.
+//!
+//! Projectiles are moved by hand every fixed step instead of being simulated
+//! by the physics engine. They hug the ground, and spin out the first kart
+//! they hit.
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use crate::kart::spin_out::SpinOut;
+use crate::kart::status::StatusEffects;
+use crate::kart::{project_on_ground_plane, Chassis};
+use crate::race::TrackPath;
+use crate::random::Random;
+
+/// Projectile plugin.
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProjectileAssets>().add_systems(
+            FixedUpdate,
+            (steer_homing_projectiles, move_projectiles).chain(),
+        );
+    }
+}
+
+/// The radius of a projectile.
+const PROJECTILE_RADIUS: f32 = 0.2;
+/// How long a projectile lasts before it disappears, in seconds.
+const PROJECTILE_LIFETIME: f32 = 10.;
+/// How long a projectile ignores the kart that fired it, in seconds.
+const OWNER_GRACE: f32 = 0.5;
+/// The largest dot product between a surface normal and up for the surface
+/// to count as a wall.
+const WALL_NORMAL_Y: f32 = 0.7;
+/// How far below a projectile the ground can be for the projectile to stick
+/// to it.
+const GROUND_SNAP: f32 = 1.;
+/// How high above the ground a projectile floats, so it doesn't scrape it.
+const GROUND_CLEARANCE: f32 = 0.05;
+/// How far ahead along the track a homing projectile aims.
+const HOMING_LOOKAHEAD: f32 = 6.;
+/// How close a homing projectile has to be to its target to aim straight
+/// at it.
+const HOMING_RADIUS: f32 = 10.;
+/// How fast a homing projectile turns, in rad/s.
+const HOMING_TURN_RATE: f32 = 4.;
+/// How long a kart spins out after being hit, in seconds.
+const SPIN_OUT_DURATION: f32 = 1.2;
+
+/// A projectile.
+#[derive(Clone, Component, Debug)]
+pub struct Projectile {
+    /// What kind of projectile it is.
+    pub kind: ProjectileKind,
+    /// The velocity of the projectile.
+    pub velocity: Vec3,
+    /// The kart that fired the projectile.
+    pub owner: Entity,
+    age: f32,
+}
+
+impl Projectile {
+    /// Creates a new projectile.
+    pub fn new(kind: ProjectileKind, velocity: Vec3, owner: Entity) -> Projectile {
+        Projectile {
+            kind,
+            velocity,
+            owner,
+            age: 0.,
+        }
+    }
+}
+
+/// The kind of a projectile.
+#[derive(Clone, Debug)]
+pub enum ProjectileKind {
+    /// Flies straight, bouncing off walls.
+    Bouncing {
+        /// How many more walls the projectile can bounce off.
+        bounces: u32,
+    },
+    /// Follows the track towards a kart.
+    Homing {
+        /// The kart the projectile is chasing.
+        target: Option<Entity>,
+    },
+}
+
+/// A bundle for a projectile.
+#[derive(Bundle)]
+pub struct ProjectileBundle {
+    pub pbr: PbrBundle,
+    pub projectile: Projectile,
+}
+
+impl ProjectileBundle {
+    /// Creates a new projectile at a position.
+    pub fn new(
+        projectile: Projectile,
+        position: Vec3,
+        assets: &ProjectileAssets,
+    ) -> ProjectileBundle {
+        let material = match projectile.kind {
+            ProjectileKind::Bouncing { .. } => assets.bouncing_material.clone(),
+            ProjectileKind::Homing { .. } => assets.homing_material.clone(),
+        };
+
+        ProjectileBundle {
+            pbr: PbrBundle {
+                mesh: assets.mesh.clone(),
+                material,
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+            projectile,
+        }
+    }
+}
+
+/// The mesh and materials of projectiles.
+#[derive(Debug, Resource)]
+pub struct ProjectileAssets {
+    mesh: Handle<Mesh>,
+    bouncing_material: Handle<StandardMaterial>,
+    homing_material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for ProjectileAssets {
+    fn from_world(world: &mut World) -> ProjectileAssets {
+        let mesh = world.resource_mut::<Assets<Mesh>>().add(
+            shape::UVSphere {
+                radius: PROJECTILE_RADIUS,
+                ..default()
+            }
+            .into(),
+        );
+
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let bouncing_material = materials.add(Color::GREEN.into());
+        let homing_material = materials.add(Color::RED.into());
+
+        ProjectileAssets {
+            mesh,
+            bouncing_material,
+            homing_material,
+        }
+    }
+}
+
+fn steer_homing_projectiles(
+    mut projectile_query: Query<(&mut Projectile, &Transform)>,
+    target_query: Query<&GlobalTransform, With<Chassis>>,
+    track_path: Res<TrackPath>,
+    time: Res<Time>,
+) {
+    for (mut projectile, transform) in projectile_query.iter_mut() {
+        let ProjectileKind::Homing { target } = projectile.kind else {
+            continue;
+        };
+
+        let position = transform.translation;
+        let target = target.and_then(|target| target_query.get(target).ok());
+
+        // follow the track until the target is close, then go straight for it
+        let aim = match target {
+            Some(target) if target.translation().distance(position) < HOMING_RADIUS => {
+                Some(target.translation())
+            }
+            _ => track_path
+                .project(position)
+                .and_then(|distance| track_path.point_at(distance + HOMING_LOOKAHEAD)),
+        };
+
+        let Some(aim) = aim else {
+            continue;
+        };
+
+        let speed = project_on_ground_plane(Vec3::Y, projectile.velocity).length();
+        let current = project_on_ground_plane(Vec3::Y, projectile.velocity).normalize_or_zero();
+        let desired = project_on_ground_plane(Vec3::Y, aim - position).normalize_or_zero();
+
+        if current == Vec3::ZERO || desired == Vec3::ZERO {
+            continue;
+        }
+
+        let max_angle = HOMING_TURN_RATE * time.delta_seconds();
+        let angle = current.angle_between(desired).min(max_angle);
+
+        // both directions are flat, so the turn is around up, and either way
+        // works when the aim is straight behind
+        let axis = if current.cross(desired).y < 0. {
+            -Vec3::Y
+        } else {
+            Vec3::Y
+        };
+        let direction = Quat::from_axis_angle(axis, angle) * current;
+
+        projectile.velocity = direction * speed + Vec3::Y * projectile.velocity.y;
+    }
+}
+
+fn move_projectiles(
+    mut commands: Commands,
+    mut projectile_query: Query<(Entity, &mut Projectile, &mut Transform)>,
+    mut kart_query: Query<(&mut SpinOut, &mut StatusEffects, &mut Velocity)>,
+    rapier_context: Res<RapierContext>,
+    mut rng: ResMut<Random>,
+    time: Res<Time>,
+) {
+    let shape = Collider::ball(PROJECTILE_RADIUS);
+
+    for (entity, mut projectile, mut transform) in projectile_query.iter_mut() {
+        projectile.age += time.delta_seconds();
+
+        if projectile.age > PROJECTILE_LIFETIME {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let mut filter = QueryFilter::new().exclude_sensors();
+
+        if projectile.age < OWNER_GRACE {
+            filter = filter.exclude_rigid_body(projectile.owner);
+        }
+
+        let motion = projectile.velocity * time.delta_seconds();
+        let distance = motion.length();
+        let direction = motion.normalize_or_zero();
+
+        let hit = rapier_context.cast_shape(
+            transform.translation,
+            Quat::IDENTITY,
+            direction,
+            &shape,
+            distance,
+            false,
+            filter,
+        );
+
+        let Some((other, toi)) = hit else {
+            transform.translation += motion;
+            stick_to_ground(&mut projectile, &mut transform, &rapier_context, &time);
+            continue;
+        };
+
+        // hit a kart, even one that can't be spun out right now
+        if let Ok((mut spin_out, mut status_effects, mut velocity)) = kart_query.get_mut(other) {
+            spin_out.start(
+                &mut status_effects,
+                &mut velocity,
+                SPIN_OUT_DURATION,
+                &mut rng,
+            );
+
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        transform.translation += direction * toi.toi;
+
+        let normal = toi.details.map(|d| d.normal1).unwrap_or(-direction);
+
+        if normal.y.abs() >= WALL_NORMAL_Y {
+            // a slope, keep following the ground
+            let speed = projectile.velocity.length();
+            projectile.velocity =
+                project_on_ground_plane(normal, projectile.velocity).normalize_or_zero() * speed;
+            continue;
+        }
+
+        match &mut projectile.kind {
+            ProjectileKind::Bouncing { bounces } if *bounces > 0 => {
+                *bounces -= 1;
+
+                let velocity = projectile.velocity;
+                projectile.velocity = velocity - 2. * velocity.dot(normal) * normal;
+            }
+            _ => commands.entity(entity).despawn_recursive(),
+        }
+    }
+}
+
+fn stick_to_ground(
+    projectile: &mut Projectile,
+    transform: &mut Transform,
+    rapier_context: &RapierContext,
+    time: &Time,
+) {
+    let filter = QueryFilter::exclude_dynamic().exclude_sensors();
+
+    let ground = rapier_context.cast_ray(
+        transform.translation,
+        Vec3::NEG_Y,
+        PROJECTILE_RADIUS + GROUND_CLEARANCE + GROUND_SNAP,
+        true,
+        filter,
+    );
+
+    match ground {
+        Some((_, toi)) => {
+            transform.translation.y += PROJECTILE_RADIUS + GROUND_CLEARANCE - toi;
+            projectile.velocity.y = 0.;
+        }
+        None => {
+            // fall off ledges
+            projectile.velocity.y -= 9.81 * time.delta_seconds();
+        }
+    }
+}
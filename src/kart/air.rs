@@ -0,0 +1,199 @@
+//! Airborne karts.
+//!
+//! While no wheel touches the ground, a kart can be pitched and yawed a
+//! little, and can perform a trick. Landing cleanly after a trick gives the
+//! kart a boost. Every landing sends a [`KartLanded`] event, as long as the
+//! kart was in the air for at least [`KartOptions::min_landing_airtime`].
+//!
+//! [`KartOptions::min_landing_airtime`]: super::KartOptions::min_landing_airtime
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use super::input::{InputSystem, PlayerCommands};
+use super::status::EffectiveOptions;
+use super::{Chassis, KartSystem};
+
+/// Air control plugin.
+pub struct AirPlugin;
+
+impl Plugin for AirPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<KartLanded>()
+            .add_systems(
+                FixedUpdate,
+                update_air_state
+                    .in_set(AirSystem::State)
+                    .after(KartSystem::GroundNormal)
+                    .after(KartSystem::Stun)
+                    .after(InputSystem::Collect),
+            )
+            .add_systems(
+                FixedUpdate,
+                (apply_air_control, apply_trick_boost)
+                    .after(AirSystem::State)
+                    .after(KartSystem::ResetForces),
+            );
+    }
+}
+
+/// Air control systems.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SystemSet)]
+pub enum AirSystem {
+    /// Updates [`AirState`] and sends [`KartLanded`] events.
+    State,
+}
+
+/// Sent when a kart lands on the ground.
+#[derive(Clone, Debug, Event)]
+pub struct KartLanded {
+    /// The kart that landed.
+    pub kart: Entity,
+    /// How long the kart was in the air, in seconds.
+    pub airtime: f32,
+    /// The speed of the kart into the ground.
+    pub impact_speed: f32,
+    /// Whether the kart performed a trick and landed cleanly.
+    pub trick: bool,
+}
+
+/// The airborne state of a kart.
+#[derive(Clone, Component, Debug, Default)]
+pub struct AirState {
+    airtime: f32,
+    trick: bool,
+    trick_held: bool,
+}
+
+impl AirState {
+    /// Checks if the kart is in the air.
+    pub fn airborne(&self) -> bool {
+        self.airtime > 0.
+    }
+
+    /// How long the kart has been in the air, in seconds.
+    pub fn airtime(&self) -> f32 {
+        self.airtime
+    }
+
+    /// Checks if the kart has performed a trick during this jump.
+    pub fn trick(&self) -> bool {
+        self.trick
+    }
+}
+
+fn update_air_state(
+    mut chassis_query: Query<(
+        Entity,
+        &mut AirState,
+        &GlobalTransform,
+        &Velocity,
+        &Chassis,
+        &PlayerCommands,
+        &EffectiveOptions,
+    )>,
+    mut landed_events: EventWriter<KartLanded>,
+    time: Res<Time>,
+) {
+    for (kart, mut air_state, transform, velocity, chassis, player_commands, options) in
+        chassis_query.iter_mut()
+    {
+        // tricks need a fresh press in the air, holding the button from
+        // before takeoff doesn't count
+        let trick_held = player_commands.commands().trick;
+        let trick_pressed = trick_held && !air_state.trick_held;
+        air_state.trick_held = trick_held;
+
+        let Some(ground_normal) = chassis.ground_normal() else {
+            air_state.airtime += time.delta_seconds();
+
+            if trick_pressed && air_state.airtime >= options.min_trick_airtime {
+                air_state.trick = true;
+            }
+
+            continue;
+        };
+
+        if !air_state.airborne() {
+            continue;
+        }
+
+        // a wheel losing the ground for a moment over a bump isn't a jump
+        if air_state.airtime < options.min_landing_airtime {
+            *air_state = AirState {
+                trick_held,
+                ..default()
+            };
+
+            continue;
+        }
+
+        let clean =
+            transform.up().angle_between(ground_normal) <= options.clean_landing_angle.to_radians();
+
+        landed_events.send(KartLanded {
+            kart,
+            airtime: air_state.airtime,
+            impact_speed: -velocity.linvel.dot(ground_normal),
+            trick: air_state.trick && clean,
+        });
+
+        *air_state = AirState {
+            trick_held,
+            ..default()
+        };
+    }
+}
+
+fn apply_air_control(
+    mut chassis_query: Query<(
+        &mut ExternalForce,
+        &GlobalTransform,
+        &ReadMassProperties,
+        &AirState,
+        &PlayerCommands,
+        &EffectiveOptions,
+    )>,
+) {
+    for (mut ef, transform, mass_properties, air_state, player_commands, options) in
+        chassis_query.iter_mut()
+    {
+        if !air_state.airborne() {
+            continue;
+        }
+
+        let mass_properties = mass_properties.get();
+        let commands = player_commands.commands();
+
+        // accelerating pitches the nose down, braking pitches it up
+        let pitch = transform.right() * -commands.acceleration * options.air_pitch;
+        let yaw = transform.up() * -commands.steering * options.air_yaw;
+
+        *ef += ExternalForce {
+            torque: (pitch + yaw) * mass_properties.mass,
+            ..default()
+        };
+    }
+}
+
+fn apply_trick_boost(
+    mut chassis_query: Query<(
+        &mut ExternalImpulse,
+        &GlobalTransform,
+        &ReadMassProperties,
+        &EffectiveOptions,
+    )>,
+    mut landed_events: EventReader<KartLanded>,
+) {
+    for ev in landed_events.read().filter(|ev| ev.trick) {
+        let Ok((mut ei, transform, mass_properties, options)) = chassis_query.get_mut(ev.kart)
+        else {
+            continue;
+        };
+
+        let mass_properties = mass_properties.get();
+
+        ei.impulse += transform.forward() * options.trick_boost * mass_properties.mass;
+    }
+}
@@ -0,0 +1,207 @@
+//! Arcade collision response for karts.
+//!
+//! Karts that touch each other are knocked apart sideways instead of relying
+//! on the physics engine's response, which tends to flip them over.
+//!
+//! Karts that hit a wall at a shallow angle are turned along it, losing a
+//! little speed. Karts that hit a wall head-on are stunned.
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use super::status::EffectiveOptions;
+use super::{project_on_ground_plane, Chassis};
+
+/// Kart collision plugin.
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<KartBumped>()
+            .add_event::<KartHitWall>()
+            .add_systems(Update, bump_karts)
+            .add_systems(Update, hit_walls.after(bump_karts));
+    }
+}
+
+/// Sent to each kart in a kart-to-kart collision.
+#[derive(Clone, Debug, Event)]
+pub struct KartBumped {
+    /// The kart that was bumped.
+    pub kart: Entity,
+    /// The kart it was bumped by.
+    pub other: Entity,
+    /// The impulse applied to the kart.
+    pub impulse: Vec3,
+}
+
+/// Sent when a kart hits a wall.
+#[derive(Clone, Debug, Event)]
+pub struct KartHitWall {
+    /// The kart that hit the wall.
+    pub kart: Entity,
+    /// The normal of the wall, pointing towards the kart.
+    pub normal: Vec3,
+    /// The speed of the kart into the wall.
+    pub impact_speed: f32,
+    /// Whether the kart hit the wall head-on.
+    pub head_on: bool,
+}
+
+/// The collision state of a kart.
+#[derive(Clone, Component, Debug, Default)]
+pub struct CollisionState {
+    previous_velocity: Vec3,
+}
+
+fn bump_karts(
+    mut kart_query: Query<(
+        &mut ExternalImpulse,
+        &mut Velocity,
+        &GlobalTransform,
+        &ReadMassProperties,
+        &EffectiveOptions,
+    )>,
+    chassis_query: Query<(), With<Chassis>>,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut bumped_events: EventWriter<KartBumped>,
+) {
+    for ev in collision_events.read() {
+        let CollisionEvent::Started(e1, e2, _) = *ev else {
+            continue;
+        };
+
+        if !chassis_query.contains(e1) || !chassis_query.contains(e2) {
+            continue;
+        }
+
+        let Ok([mut kart1, mut kart2]) = kart_query.get_many_mut([e1, e2]) else {
+            continue;
+        };
+
+        let up = (kart1.2.up() + kart2.2.up()).normalize_or_zero();
+
+        // the direction from the first kart to the second, along the ground
+        let offset = kart2.2.translation() - kart1.2.translation();
+        let normal = project_on_ground_plane(up, offset).normalize_or_zero();
+
+        if normal == Vec3::ZERO {
+            continue;
+        }
+
+        let mass1 = kart1.3.get().mass;
+        let mass2 = kart2.3.get().mass;
+        let closing_speed = (kart1.1.linvel - kart2.1.linvel).dot(normal).max(0.);
+
+        for ((ei, velocity, transform, _, options), mass, other_mass, normal, kart, other) in [
+            (&mut kart1, mass1, mass2, -normal, e1, e2),
+            (&mut kart2, mass2, mass1, normal, e2, e1),
+        ] {
+            // knock the kart towards whichever of its sides faces away from
+            // the other kart, even when bumped from behind or head-on
+            let right = project_on_ground_plane(up, transform.right()).normalize_or_zero();
+            let direction = if normal.dot(right) >= 0. {
+                right
+            } else {
+                -right
+            };
+
+            // lighter karts are knocked further
+            let share = other_mass / (mass + other_mass).max(f32::EPSILON);
+            let speed = options.bump_impulse + closing_speed * options.bump_speed_factor;
+            let impulse = direction * speed * share * mass;
+
+            ei.impulse += impulse;
+
+            // drop any roll from the contact, keep the yaw
+            let kart_up = transform.up();
+            velocity.angvel = kart_up * velocity.angvel.dot(kart_up);
+
+            bumped_events.send(KartBumped {
+                kart,
+                other,
+                impulse,
+            });
+        }
+    }
+}
+
+fn hit_walls(
+    mut kart_query: Query<(
+        Entity,
+        &mut Velocity,
+        &mut CollisionState,
+        &mut Chassis,
+        &GlobalTransform,
+        &EffectiveOptions,
+    )>,
+    chassis_query: Query<(), With<Chassis>>,
+    mut wall_events: EventWriter<KartHitWall>,
+    rapier_context: Res<RapierContext>,
+) {
+    for (kart, mut velocity, mut collision_state, mut chassis, transform, options) in
+        kart_query.iter_mut()
+    {
+        // the physics engine has already responded to the contact, so work
+        // with the velocity from before it
+        let previous_velocity = collision_state.previous_velocity;
+        let up = transform.up();
+
+        let wall_normal = rapier_context
+            .contacts_with(kart)
+            .filter(|pair| pair.has_any_active_contacts())
+            .filter_map(|pair| {
+                let (other, sign) = if pair.collider1() == kart {
+                    (pair.collider2(), -1.)
+                } else {
+                    (pair.collider1(), 1.)
+                };
+
+                if chassis_query.contains(other) {
+                    return None;
+                }
+
+                pair.manifolds()
+                    .filter(|manifold| manifold.num_points() > 0)
+                    .map(|manifold| manifold.normal() * sign)
+                    .find(|normal| normal.dot(up) < options.wall_angle.to_radians().cos())
+            })
+            // the wall the kart is driving into the hardest
+            .min_by(|a, b| {
+                a.dot(previous_velocity)
+                    .total_cmp(&b.dot(previous_velocity))
+            });
+
+        if let Some(normal) = wall_normal {
+            let impact_speed = -previous_velocity.dot(normal);
+
+            if impact_speed >= options.min_wall_impact_speed {
+                let incidence = (impact_speed / previous_velocity.length()).asin();
+                let head_on = incidence >= options.head_on_angle.to_radians();
+
+                if head_on {
+                    velocity.linvel = normal * impact_speed * options.wall_bounce;
+                    chassis.stun(options.head_on_stun);
+                } else {
+                    // slide along the wall, but never up it
+                    let along = project_on_ground_plane(normal, previous_velocity);
+                    let along = along - up * along.dot(up).max(0.);
+
+                    velocity.linvel = along * (1. - options.glancing_speed_penalty);
+                }
+
+                velocity.angvel = up * velocity.angvel.dot(up);
+
+                wall_events.send(KartHitWall {
+                    kart,
+                    normal,
+                    impact_speed,
+                    head_on,
+                });
+            }
+        }
+
+        collision_state.previous_velocity = velocity.linvel;
+    }
+}
@@ -0,0 +1,312 @@
+//! Data-driven kart definitions.
+//!
+//! Every kart points at a [`KartDef`] asset, loaded from a `.kart.ron` file.
+//! The definition describes everything that makes one kart handle differently
+//! from another: its [`KartOptions`], [`Chassis`], collider, mass and wheel
+//! layout. When the definition is loaded (or reloaded, with the `hot_reload`
+//! feature) it is applied to every kart that uses it. Karts without a
+//! definition use [`KartDef::default`].
+
+use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::{thiserror, BoxedFuture, HashSet};
+
+use bevy_rapier3d::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+use thiserror::Error;
+
+use super::wheel::{Axle, Wheel, WheelBundle, WheelCast, WheelOverrides};
+use super::{Chassis, KartOptions};
+
+/// Kart definition plugin.
+pub struct KartDefPlugin;
+
+impl Plugin for KartDefPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<KartDef>()
+            .init_asset_loader::<KartDefLoader>()
+            .add_systems(Update, apply_kart_defs.in_set(KartDefSystem::Apply));
+    }
+}
+
+/// Kart definition systems.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SystemSet)]
+pub enum KartDefSystem {
+    /// Applies loaded [`KartDef`]s to karts.
+    Apply,
+}
+
+/// A kart definition.
+#[derive(Asset, Clone, Debug, Deserialize, Serialize, TypePath)]
+#[serde(default)]
+pub struct KartDef {
+    /// The movement options of the kart.
+    pub options: KartOptions,
+    /// The chassis physics properties of the kart.
+    pub chassis: Chassis,
+    /// The shape of the kart's collider.
+    pub collider: KartCollider,
+    /// The mass of the kart in kg.
+    pub mass: f32,
+    /// Offset of the center of mass from the center of the collider.
+    pub center_of_mass: Vec3,
+    /// The wheels of the kart.
+    pub wheels: Vec<WheelDef>,
+    /// The path to the model of the kart, if it has one.
+    pub model: Option<String>,
+}
+
+impl KartDef {
+    /// Computes the mass properties of the kart.
+    pub fn mass_properties(&self) -> MassProperties {
+        let collider = self.collider.collider();
+
+        let mut mass_properties = collider.raw.mass_properties(1.);
+        mass_properties.set_mass(self.mass, true);
+
+        let mut mass_properties = MassProperties::from_rapier(mass_properties, 1.);
+        mass_properties.local_center_of_mass += self.center_of_mass;
+        mass_properties
+    }
+}
+
+impl Default for KartDef {
+    fn default() -> KartDef {
+        KartDef {
+            options: default(),
+            chassis: default(),
+            collider: default(),
+            mass: 20.,
+            center_of_mass: Vec3::ZERO,
+            wheels: vec![
+                // back right wheel
+                WheelDef::new(Vec3::new(0.35, -0.16, 0.5), Axle::Rear),
+                // back left wheel
+                WheelDef::new(Vec3::new(-0.35, -0.16, 0.5), Axle::Rear),
+                // front right wheel
+                WheelDef::new(Vec3::new(0.35, -0.16, -0.5), Axle::Front),
+                // front left wheel
+                WheelDef::new(Vec3::new(-0.35, -0.16, -0.5), Axle::Front),
+            ],
+            model: None,
+        }
+    }
+}
+
+/// The shape of a kart's collider.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum KartCollider {
+    /// A box.
+    Cuboid {
+        /// Half of the size of the box on each axis.
+        half_extents: Vec3,
+    },
+    /// A capsule lying along the forward axis.
+    Capsule {
+        /// Half of the length of the capsule's segment.
+        half_length: f32,
+        /// The radius of the capsule.
+        radius: f32,
+    },
+    /// A sphere.
+    Ball {
+        /// The radius of the sphere.
+        radius: f32,
+    },
+}
+
+impl KartCollider {
+    /// Creates a [`Collider`] from the shape.
+    pub fn collider(&self) -> Collider {
+        match *self {
+            KartCollider::Cuboid { half_extents } => {
+                Collider::cuboid(half_extents.x, half_extents.y, half_extents.z)
+            }
+            KartCollider::Capsule {
+                half_length,
+                radius,
+            } => Collider::capsule_z(half_length, radius),
+            KartCollider::Ball { radius } => Collider::ball(radius),
+        }
+    }
+}
+
+impl Default for KartCollider {
+    fn default() -> KartCollider {
+        KartCollider::Cuboid {
+            half_extents: Vec3::new(0.35, 0.166, 0.5),
+        }
+    }
+}
+
+/// The definition of a single wheel.
+///
+/// See [`Wheel`] for the meaning of each field.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WheelDef {
+    /// The position of the suspension, relative to the chassis.
+    pub position: Vec3,
+    /// The axle the wheel is on.
+    pub axle: Axle,
+    /// Settings that replace the settings of the axle for this wheel.
+    pub overrides: WheelOverrides,
+    /// The radius of the wheel.
+    pub radius: f32,
+    /// The width of the wheel.
+    pub width: f32,
+    /// How the wheel finds the ground.
+    pub cast: WheelCast,
+}
+
+impl WheelDef {
+    /// Creates a new wheel definition at a position.
+    pub fn new(position: Vec3, axle: Axle) -> WheelDef {
+        WheelDef {
+            position,
+            axle,
+            ..default()
+        }
+    }
+
+    /// Creates the [`Wheel`] for this definition.
+    pub fn wheel(&self) -> Wheel {
+        let mut wheel = Wheel::new(self.position, Wheel::default().max_suspension);
+
+        wheel.axle = self.axle;
+        wheel.overrides = self.overrides.clone();
+        wheel.radius = self.radius;
+        wheel.width = self.width;
+        wheel.cast = self.cast;
+        wheel
+    }
+}
+
+impl Default for WheelDef {
+    fn default() -> WheelDef {
+        let wheel = Wheel::default();
+
+        WheelDef {
+            position: wheel.position,
+            axle: wheel.axle,
+            overrides: wheel.overrides,
+            radius: wheel.radius,
+            width: wheel.width,
+            cast: wheel.cast,
+        }
+    }
+}
+
+/// A marker component for the model of a kart.
+#[derive(Clone, Component, Debug, Default)]
+pub struct KartModel;
+
+/// Loads [`KartDef`]s from `.kart.ron` files.
+#[derive(Default)]
+pub struct KartDefLoader;
+
+/// An error that can occur while loading a [`KartDef`].
+#[derive(Debug, Error)]
+pub enum KartDefLoaderError {
+    /// An IO error.
+    #[error("could not read kart def: {0}")]
+    Io(#[from] std::io::Error),
+    /// A RON error.
+    #[error("could not parse kart def: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for KartDefLoader {
+    type Asset = KartDef;
+    type Settings = ();
+    type Error = KartDefLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<KartDef, KartDefLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+
+            Ok(ron::de::from_bytes::<KartDef>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["kart.ron"]
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_kart_defs(
+    mut commands: Commands,
+    mut def_events: EventReader<AssetEvent<KartDef>>,
+    kart_query: Query<(Entity, Ref<Handle<KartDef>>, Option<&Children>)>,
+    children_query: Query<(), Or<(With<Wheel>, With<KartModel>)>>,
+    defs: Res<Assets<KartDef>>,
+    asset_server: Res<AssetServer>,
+) {
+    let changed_defs = def_events
+        .read()
+        .filter_map(|ev| match ev {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    let fallback = KartDef::default();
+
+    for (kart, def_handle, children) in kart_query.iter() {
+        if !def_handle.is_changed() && !changed_defs.contains(&def_handle.id()) {
+            continue;
+        }
+
+        // karts without a def get the default one, with the baseline wheels
+        let def = if *def_handle == Handle::default() {
+            &fallback
+        } else if let Some(def) = defs.get(def_handle.id()) {
+            def
+        } else {
+            continue;
+        };
+
+        // remove the old wheels and model
+        for &child in children.into_iter().flatten() {
+            if children_query.contains(child) {
+                commands.entity(child).despawn_recursive();
+            }
+        }
+
+        commands
+            .entity(kart)
+            .insert((
+                def.collider.collider(),
+                ColliderMassProperties::MassProperties(def.mass_properties()),
+                def.options.clone(),
+                def.chassis.clone(),
+            ))
+            .with_children(|parent| {
+                for wheel in def.wheels.iter() {
+                    parent.spawn(WheelBundle {
+                        wheel: wheel.wheel(),
+                        ..default()
+                    });
+                }
+
+                if let Some(model) = &def.model {
+                    parent.spawn((
+                        SceneBundle {
+                            scene: asset_server.load(model),
+                            ..default()
+                        },
+                        KartModel,
+                    ));
+                }
+            });
+    }
+}
@@ -2,6 +2,8 @@
 
 use bevy::prelude::*;
 
+use super::LocalPlayer;
+
 /// Kart input plugin.
 pub struct InputPlugin;
 
@@ -31,6 +33,23 @@ impl PlayerCommands {
     pub fn commands(&self) -> Commands {
         self.commands.clone()
     }
+
+    /// Sets the inputs for this frame, for karts that aren't driven by
+    /// [`LocalPlayer`] inputs.
+    pub fn set(&mut self, commands: Commands) {
+        self.commands = commands;
+    }
+
+    /// Checks if the held item should be used, and marks the press as
+    /// handled.
+    pub fn take_use_item(&mut self) -> bool {
+        std::mem::take(&mut self.commands.use_item)
+    }
+
+    /// Discards the inputs for this frame.
+    pub fn clear(&mut self) {
+        self.commands = Commands::default();
+    }
 }
 
 /// The list of inputs a player can have on a frame.
@@ -40,16 +59,32 @@ impl PlayerCommands {
 pub struct Commands {
     /// The acceleration of the input.
     ///
-    /// `1.` is maximum forward acceleration, `-1.` is maximum backward
-    /// acceleration.
+    /// `1.` is maximum forward acceleration, `-1.` is maximum braking. Once
+    /// the kart is stopped, `-1.` is maximum backward acceleration.
     pub acceleration: f32,
+    /// The steering of the input.
+    ///
+    /// `1.` is steering fully right, `-1.` is steering fully left.
+    pub steering: f32,
+    /// Whether the trick button is held.
+    pub trick: bool,
+    /// Whether the held item should be used.
+    ///
+    /// Stays set until a fixed step uses the item, see
+    /// [`PlayerCommands::take_use_item`].
+    pub use_item: bool,
+    /// Whether the look back button is held.
+    pub look_back: bool,
 }
 
 fn collect_local_inputs(
-    mut collector_query: Query<&mut PlayerCommands>,
+    mut collector_query: Query<&mut PlayerCommands, With<LocalPlayer>>,
     keyboard_input: Res<Input<KeyCode>>,
 ) {
     for mut collector in collector_query.iter_mut() {
+        // keep presses the fixed step hasn't seen yet
+        let use_item = collector.commands.use_item;
+
         // reset inputs
         collector.commands = Commands::default();
 
@@ -61,5 +96,17 @@ fn collect_local_inputs(
         if keyboard_input.pressed(KeyCode::S) {
             collector.commands.acceleration -= 1.;
         }
+
+        if keyboard_input.pressed(KeyCode::D) {
+            collector.commands.steering += 1.;
+        }
+
+        if keyboard_input.pressed(KeyCode::A) {
+            collector.commands.steering -= 1.;
+        }
+
+        collector.commands.trick = keyboard_input.pressed(KeyCode::Space);
+        collector.commands.use_item = use_item || keyboard_input.just_pressed(KeyCode::E);
+        collector.commands.look_back = keyboard_input.pressed(KeyCode::Q);
     }
 }
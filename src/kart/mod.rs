@@ -1,17 +1,32 @@
 //! Kart phyiscs.
 
+pub mod air;
+pub mod collision;
+pub mod def;
 pub mod input;
+pub mod spin_out;
+pub mod stabilizer;
+pub mod status;
+pub mod tuning;
 pub mod wheel;
 
 use bevy::prelude::*;
 
 use bevy_rapier3d::prelude::*;
 
-use crate::camera::FollowKartBundle;
+use serde::{Deserialize, Serialize};
+
+use crate::item::HeldItem;
+use crate::race::{RacePosition, RaceProgress};
 use crate::GameState;
 
+use air::AirState;
+use collision::CollisionState;
+use def::KartDef;
 use input::{InputSystem, PlayerCommands};
-use wheel::{Wheel, WheelBundle, WheelSystem};
+use spin_out::SpinOut;
+use status::{EffectiveOptions, Stat, StatusEffects};
+use wheel::{Axle, AxleSettings, Wheel, WheelSystem};
 
 /// Kart plugin.
 pub struct KartPlugin;
@@ -20,27 +35,47 @@ impl Plugin for KartPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Chassis>()
             .register_type::<KartOptions>()
+            .register_type::<StatusEffects>()
+            .register_type::<EffectiveOptions>()
             .add_systems(OnEnter(GameState::InGame), spawn_local_player)
             .add_systems(
                 FixedUpdate,
-                propagate_chassis_properties.before(WheelSystem::Raycast),
+                propagate_chassis_properties
+                    .after(KartSystem::Status)
+                    .before(WheelSystem::Raycast),
             )
             .add_systems(
                 FixedUpdate,
-                average_chassis_normals.after(WheelSystem::Raycast),
+                average_chassis_normals
+                    .in_set(KartSystem::GroundNormal)
+                    .after(WheelSystem::Raycast),
             )
             .add_systems(
                 FixedUpdate,
                 apply_chassis_acceleration
                     .after(WheelSystem::Raycast)
                     .after(InputSystem::Collect)
+                    .after(KartSystem::Stun)
                     .after(KartSystem::ResetForces),
             )
             .add_systems(
                 FixedUpdate,
-                reset_chassis_forces.in_set(KartSystem::ResetForces),
+                apply_chassis_steering
+                    .after(KartSystem::GroundNormal)
+                    .after(InputSystem::Collect)
+                    .after(KartSystem::Stun)
+                    .after(KartSystem::ResetForces),
             )
-            .add_systems(Update, create_kart_wheels);
+            .add_systems(
+                FixedUpdate,
+                stun_chassis
+                    .in_set(KartSystem::Stun)
+                    .after(InputSystem::Collect),
+            )
+            .add_systems(
+                FixedUpdate,
+                reset_chassis_forces.in_set(KartSystem::ResetForces),
+            );
     }
 }
 
@@ -49,9 +84,19 @@ impl Plugin for KartPlugin {
 pub enum KartSystem {
     /// Resets the external forces acting on a kart.
     ResetForces,
+    /// Averages the normals of the wheels into [`Chassis::ground_normal`].
+    GroundNormal,
+    /// Counts down status effects and updates [`EffectiveOptions`].
+    Status,
+    /// Counts down stuns and spin outs, and discards the inputs of karts
+    /// that can't be controlled.
+    Stun,
 }
 
 /// Kart bundle.
+///
+/// The collider, mass, options and wheels of the kart are replaced by its
+/// [`KartDef`] once it loads. Without a def, the default [`KartDef`] is used.
 #[derive(Bundle, Clone, Debug)]
 pub struct KartBundle {
     pub transform: Transform,
@@ -61,14 +106,24 @@ pub struct KartBundle {
     pub view_visibility: ViewVisibility,
     pub rigidbody: RigidBody,
     pub collider: Collider,
+    pub active_events: ActiveEvents,
     pub collider_mass_properties: ColliderMassProperties,
     pub external_force: ExternalForce,
     pub external_impulse: ExternalImpulse,
     pub velocity: Velocity,
     pub mass_properties: ReadMassProperties,
     pub kart_options: KartOptions,
+    pub status_effects: StatusEffects,
+    pub effective_options: EffectiveOptions,
     pub chassis: Chassis,
+    pub spin_out: SpinOut,
     pub player_commands: PlayerCommands,
+    pub air_state: AirState,
+    pub collision_state: CollisionState,
+    pub held_item: HeldItem,
+    pub race_progress: RaceProgress,
+    pub race_position: RacePosition,
+    pub def: Handle<KartDef>,
 }
 
 impl Default for KartBundle {
@@ -80,53 +135,211 @@ impl Default for KartBundle {
             inherited_visibility: default(),
             view_visibility: default(),
             rigidbody: RigidBody::Dynamic,
-            // TODO tweak as needed
             collider: Collider::cuboid(0.35, 0.166, 0.5),
+            active_events: ActiveEvents::COLLISION_EVENTS,
             collider_mass_properties: ColliderMassProperties::Mass(20.),
             external_force: default(),
             external_impulse: default(),
             velocity: default(),
             mass_properties: default(),
             kart_options: default(),
+            status_effects: default(),
+            effective_options: default(),
             chassis: default(),
+            spin_out: default(),
             player_commands: default(),
+            air_state: default(),
+            collision_state: default(),
+            held_item: default(),
+            race_progress: default(),
+            race_position: default(),
+            def: default(),
         }
     }
 }
 
 /// Kart movement options.
-#[derive(Clone, Component, Debug, Reflect)]
+#[derive(Clone, Component, Debug, Deserialize, Reflect, Serialize)]
+#[serde(default)]
 pub struct KartOptions {
     /// How fast the kart accelerates in m/s^3.
     ///
     /// Mass is ignored.
     pub max_acceleration: f32,
+    /// How the acceleration tapers off as the kart approaches its top speed.
+    pub acceleration_curve: AccelerationCurve,
     /// The maximum velocity the kart can achieve alone.
     pub max_velocity: f32,
+    /// The ratio of its top speed the kart keeps while driving offroad.
+    pub offroad_velocity: f32,
+    /// How fast the kart slows down while braking in m/s^2.
+    pub brake_deceleration: f32,
+    /// How fast the kart accelerates in reverse in m/s^2.
+    pub reverse_acceleration: f32,
+    /// The maximum velocity the kart can achieve in reverse.
+    pub max_reverse_velocity: f32,
+    /// How fast the kart slows down while coasting in m/s^2.
+    pub rolling_resistance: f32,
+    /// How fast the kart turns at full steering, in rad/s.
+    pub turn_rate: f32,
+    /// How quickly the kart reaches its turn rate.
+    pub steering_response: f32,
+    /// The speed at which the kart can turn at its full turn rate.
+    pub full_turn_velocity: f32,
+    /// How strongly the kart can pitch itself while airborne, per unit of
+    /// mass.
+    pub air_pitch: f32,
+    /// How strongly the kart can yaw itself while airborne, per unit of mass.
+    pub air_yaw: f32,
+    /// How long the kart must be in the air for touching the ground again to
+    /// count as a landing, in seconds.
+    pub min_landing_airtime: f32,
+    /// How long the kart must be in the air before it can do a trick, in
+    /// seconds.
+    pub min_trick_airtime: f32,
+    /// The largest angle, in degrees, between the kart and the ground that
+    /// still counts as a clean landing.
+    pub clean_landing_angle: f32,
+    /// The velocity gained by landing a trick.
+    pub trick_boost: f32,
+    /// The velocity a kart is knocked away with when bumping another kart.
+    pub bump_impulse: f32,
+    /// How much of the closing speed of a bump is added to the knockback.
+    pub bump_speed_factor: f32,
+    /// The smallest angle, in degrees, between the kart's up direction and a
+    /// surface for the surface to count as a wall.
+    pub wall_angle: f32,
+    /// The slowest the kart can hit a wall for it to count as a hit.
+    pub min_wall_impact_speed: f32,
+    /// The smallest angle, in degrees, between the kart's velocity and a wall
+    /// for a hit to count as head-on.
+    pub head_on_angle: f32,
+    /// The ratio of speed lost when glancing off a wall.
+    pub glancing_speed_penalty: f32,
+    /// How much of the impact speed the kart bounces back with after a
+    /// head-on hit.
+    pub wall_bounce: f32,
+    /// How long the kart is stunned after a head-on hit, in seconds.
+    pub head_on_stun: f32,
+    /// How long the kart can't be spun out again after a spin out, in
+    /// seconds.
+    pub spin_out_invulnerability: f32,
 }
 
 impl Default for KartOptions {
     fn default() -> KartOptions {
         KartOptions {
             max_acceleration: 30.,
+            acceleration_curve: default(),
             max_velocity: 12.,
+            offroad_velocity: 0.6,
+            brake_deceleration: 40.,
+            reverse_acceleration: 15.,
+            max_reverse_velocity: 5.,
+            rolling_resistance: 3.,
+            turn_rate: 2.5,
+            steering_response: 12.,
+            full_turn_velocity: 4.,
+            air_pitch: 4.,
+            air_yaw: 3.,
+            min_landing_airtime: 0.1,
+            min_trick_airtime: 0.2,
+            clean_landing_angle: 25.,
+            trick_boost: 4.,
+            bump_impulse: 2.,
+            bump_speed_factor: 0.5,
+            wall_angle: 60.,
+            min_wall_impact_speed: 1.,
+            head_on_angle: 50.,
+            glancing_speed_penalty: 0.15,
+            wall_bounce: 0.3,
+            head_on_stun: 0.6,
+            spin_out_invulnerability: 1.5,
         }
     }
 }
 
+/// The shape of a kart's acceleration as it approaches its top speed.
+#[derive(Clone, Debug, Deserialize, Reflect, Serialize)]
+pub enum AccelerationCurve {
+    /// The acceleration is scaled by `1 - t^exponent`, where `t` is the ratio
+    /// of the velocity to the top speed.
+    ///
+    /// Higher exponents keep more acceleration near the top speed.
+    Power {
+        /// The exponent of the curve.
+        exponent: f32,
+    },
+    /// The acceleration is linearly interpolated between samples.
+    ///
+    /// Each sample is the ratio of the velocity to the top speed, and the
+    /// ratio of the acceleration to [`KartOptions::max_acceleration`]. The
+    /// samples must be sorted by velocity.
+    Sampled(Vec<Vec2>),
+}
+
+impl AccelerationCurve {
+    /// Samples the curve at `t`, the ratio of the velocity to the top speed.
+    ///
+    /// Returns the ratio of the acceleration to
+    /// [`KartOptions::max_acceleration`].
+    pub fn sample(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+
+        match self {
+            AccelerationCurve::Power { exponent } => 1. - t.powf(*exponent),
+            AccelerationCurve::Sampled(samples) => {
+                let Some(next) = samples.iter().position(|s| s.x > t) else {
+                    return samples.last().map(|s| s.y).unwrap_or(0.);
+                };
+
+                if next == 0 {
+                    return samples[0].y;
+                }
+
+                let (a, b) = (samples[next - 1], samples[next]);
+
+                a.y + (b.y - a.y) * (t - a.x) / (b.x - a.x)
+            }
+        }
+    }
+}
+
+impl Default for AccelerationCurve {
+    fn default() -> AccelerationCurve {
+        AccelerationCurve::Power { exponent: 2. }
+    }
+}
+
 /// Kart chassis physics properties.
 ///
-/// Copies its properties to the children [`Wheel`]s.
-#[derive(Clone, Component, Debug, Reflect)]
+/// Copies the settings of each axle to the children [`Wheel`]s on that axle.
+/// Per-wheel overrides set in [`Wheel::overrides`] take precedence.
+#[derive(Clone, Component, Debug, Deserialize, Reflect, Serialize)]
+#[serde(default)]
 pub struct Chassis {
-    /// The maximum distance for suspension.
-    pub max_suspension: f32,
-    /// The maximum force applied by the suspension.
-    pub max_force: f32,
-    /// The damping factor of the suspension.
-    pub damping_factor: f32,
+    /// The suspension and grip of the front wheels.
+    pub front: AxleSettings,
+    /// The suspension and grip of the rear wheels.
+    pub rear: AxleSettings,
+    /// How strongly the kart aligns itself with the ground, per unit of mass.
+    pub upright_strength: f32,
+    /// How strongly the kart aligns itself with [`Chassis::air_up`] while
+    /// airborne, per unit of mass.
+    pub air_upright_strength: f32,
+    /// Damps the pitch and roll of the kart while aligning, per unit of mass.
+    pub upright_damping: f32,
+    /// The up direction the kart aligns itself with while airborne.
+    pub air_up: Vec3,
+    /// How strongly wheels on the same axle resist body roll, per unit of
+    /// mass.
+    pub anti_roll: f32,
+    #[serde(skip)]
     wheels_contacting_ground: usize,
+    #[serde(skip)]
     ground_normal: Option<Vec3>,
+    #[serde(skip)]
+    stun: f32,
 }
 
 impl Chassis {
@@ -134,52 +347,79 @@ impl Chassis {
     pub fn ground_normal(&self) -> Option<Vec3> {
         self.ground_normal
     }
+
+    /// The number of wheels touching the ground.
+    pub fn wheels_contacting_ground(&self) -> usize {
+        self.wheels_contacting_ground
+    }
+
+    /// Checks if the kart is stunned.
+    ///
+    /// Stunned karts ignore their [`PlayerCommands`].
+    pub fn stunned(&self) -> bool {
+        self.stun > 0.
+    }
+
+    /// Stuns the kart for `duration` seconds.
+    ///
+    /// Does nothing if the kart is already stunned for longer.
+    pub fn stun(&mut self, duration: f32) {
+        self.stun = self.stun.max(duration);
+    }
+
+    /// The settings of an axle.
+    pub fn axle(&self, axle: Axle) -> &AxleSettings {
+        match axle {
+            Axle::Front => &self.front,
+            Axle::Rear => &self.rear,
+        }
+    }
+
+    /// Copies the tunable properties of another chassis, keeping the
+    /// simulation state of this one.
+    pub fn set_tuning(&mut self, tuning: &Chassis) {
+        *self = Chassis {
+            wheels_contacting_ground: self.wheels_contacting_ground,
+            ground_normal: self.ground_normal,
+            stun: self.stun,
+            ..tuning.clone()
+        };
+    }
 }
 
 impl Default for Chassis {
     fn default() -> Chassis {
         Chassis {
-            max_suspension: 0.35,
-            max_force: 4.,
-            damping_factor: 0.2,
+            front: default(),
+            rear: default(),
+            upright_strength: 40.,
+            air_upright_strength: 8.,
+            upright_damping: 6.,
+            air_up: Vec3::Y,
+            anti_roll: 6.,
             wheels_contacting_ground: 0,
             ground_normal: None,
+            stun: 0.,
         }
     }
 }
 
+/// Where the local player starts.
+pub const PLAYER_START: Vec3 = Vec3::new(-8., 7.5, 0.);
+
 /// A marker component for the local player.
 #[derive(Clone, Component, Debug, Default)]
 pub struct LocalPlayer;
 
-fn spawn_local_player(mut commands: Commands) {
-    let kart = commands
-        .spawn((
-            KartBundle {
-                transform: Transform::from_xyz(-8., 7.5, 0.),
-                ..default()
-            },
-            LocalPlayer,
-        ))
-        .id();
-
-    commands.spawn((Camera3dBundle::default(), FollowKartBundle::new(kart)));
-}
-
-fn create_kart_wheels(mut commands: Commands, new_karts_query: Query<Entity, Added<Chassis>>) {
-    for kart in new_karts_query.iter() {
-        //Collider::cuboid(0.35, 0.166, 0.5)
-        commands.entity(kart).with_children(|parent| {
-            // front right wheel
-            parent.spawn(WheelBundle::new(Vec3::new(0.35, -0.16, 0.5)));
-            // front left wheel
-            parent.spawn(WheelBundle::new(Vec3::new(-0.35, -0.16, 0.5)));
-            // back right wheel
-            parent.spawn(WheelBundle::new(Vec3::new(0.35, -0.16, -0.5)));
-            // back left wheel
-            parent.spawn(WheelBundle::new(Vec3::new(-0.35, -0.16, -0.5)));
-        });
-    }
+fn spawn_local_player(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        KartBundle {
+            transform: Transform::from_translation(PLAYER_START),
+            def: asset_server.load("karts/default.kart.ron"),
+            ..default()
+        },
+        LocalPlayer,
+    ));
 }
 
 fn reset_chassis_forces(mut chassis_query: Query<&mut ExternalForce, With<Chassis>>) {
@@ -188,17 +428,33 @@ fn reset_chassis_forces(mut chassis_query: Query<&mut ExternalForce, With<Chassi
     }
 }
 
+fn stun_chassis(mut chassis_query: Query<(&mut Chassis, &mut PlayerCommands)>, time: Res<Time>) {
+    for (mut chassis, mut player_commands) in chassis_query.iter_mut() {
+        if !chassis.stunned() {
+            continue;
+        }
+
+        chassis.stun = (chassis.stun - time.delta_seconds()).max(0.);
+        player_commands.clear();
+    }
+}
+
 fn propagate_chassis_properties(
-    chassis_query: Query<(&Chassis, &Children)>,
+    chassis_query: Query<(&Chassis, &StatusEffects, &Children)>,
     mut wheels_query: Query<&mut Wheel>,
 ) {
-    for (chassis, children) in chassis_query.iter() {
+    for (chassis, status_effects, children) in chassis_query.iter() {
         let mut wheels = wheels_query.iter_many_mut(children);
 
         while let Some(mut wheel) = wheels.fetch_next() {
-            wheel.max_force = chassis.max_force;
-            wheel.max_suspension = chassis.max_suspension;
-            wheel.damping_factor = chassis.damping_factor;
+            let settings = wheel.overrides.apply(chassis.axle(wheel.axle));
+
+            wheel.max_suspension = settings.max_suspension;
+            wheel.rest_length = settings.rest_length;
+            wheel.stiffness = settings.stiffness;
+            wheel.compression_damping = settings.compression_damping;
+            wheel.rebound_damping = settings.rebound_damping;
+            wheel.grip = status_effects.apply(Stat::Grip, settings.grip);
         }
     }
 }
@@ -230,9 +486,9 @@ fn apply_chassis_acceleration(
         &ReadMassProperties,
         &Chassis,
         &PlayerCommands,
-        &KartOptions,
+        &EffectiveOptions,
     )>,
-    //time: Res<Time>,
+    time: Res<Time>,
 ) {
     for (mut ef, transform, velocity, mass_properties, chassis, player_commands, options) in
         chassis_query.iter_mut()
@@ -250,17 +506,122 @@ fn apply_chassis_acceleration(
 
         // get current velocity in forward direction
         let forward_velocity = z_axis.dot(velocity.linvel);
+        let top_speed = options.max_velocity;
+
+        let acceleration = if acceleration > 0. && forward_velocity > top_speed {
+            // ease back down when the top speed drops, like after a boost
+            decelerate(
+                forward_velocity - top_speed,
+                options.rolling_resistance,
+                &time,
+            )
+        } else if acceleration > 0. {
+            if forward_velocity < -STOPPED_VELOCITY {
+                // brake out of reverse
+                decelerate(
+                    forward_velocity,
+                    options.brake_deceleration * acceleration,
+                    &time,
+                )
+            } else {
+                let t = forward_velocity / top_speed.max(f32::EPSILON);
+
+                options.max_acceleration * options.acceleration_curve.sample(t) * acceleration
+            }
+        } else if acceleration < 0. {
+            if forward_velocity > STOPPED_VELOCITY {
+                // brake, only start reversing after a stop
+                decelerate(
+                    forward_velocity,
+                    options.brake_deceleration * -acceleration,
+                    &time,
+                )
+            } else if forward_velocity > -options.max_reverse_velocity {
+                options.reverse_acceleration * acceleration
+            } else {
+                0.
+            }
+        } else {
+            // coast
+            decelerate(forward_velocity, options.rolling_resistance, &time)
+        };
+
+        *ef += ExternalForce {
+            force: z_axis * acceleration * mass_properties.mass,
+            ..default()
+        };
+    }
+}
+
+/// The forward velocity under which a kart is considered stopped.
+const STOPPED_VELOCITY: f32 = 0.5;
+
+/// Gets the acceleration that slows down `velocity` by `deceleration`,
+/// without overshooting past a stop.
+fn decelerate(velocity: f32, deceleration: f32, time: &Time) -> f32 {
+    let max_deceleration = velocity.abs() / time.delta_seconds().max(f32::EPSILON);
+
+    -velocity.signum() * deceleration.min(max_deceleration)
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_chassis_steering(
+    mut chassis_query: Query<(
+        &mut ExternalForce,
+        &GlobalTransform,
+        &Velocity,
+        &ReadMassProperties,
+        &Chassis,
+        &SpinOut,
+        &PlayerCommands,
+        &EffectiveOptions,
+    )>,
+) {
+    for (
+        mut ef,
+        transform,
+        velocity,
+        mass_properties,
+        chassis,
+        spin_out,
+        player_commands,
+        options,
+    ) in chassis_query.iter_mut()
+    {
+        // get normal, only steer if it is grounded
+        let Some(ground_normal) = chassis.ground_normal() else {
+            continue;
+        };
+
+        // don't fight the spin
+        if spin_out.spinning() {
+            continue;
+        }
 
-        let force = (options.max_acceleration * mass_properties.mass) * acceleration;
+        let steering = player_commands.commands().steering;
 
-        if (force < 0. && forward_velocity > -options.max_velocity)
-            || (force > 0. && forward_velocity < options.max_velocity)
-        {
-            *ef += ExternalForce {
-                force: z_axis * force,
-                ..default()
-            };
+        // with the wheel let go the kart keeps whatever spin it has, like
+        // after a bump
+        if steering == 0. {
+            continue;
         }
+
+        let mass_properties = mass_properties.get();
+
+        let z_axis = project_on_ground_plane(ground_normal, transform.forward()).normalize();
+        let forward_velocity = z_axis.dot(velocity.linvel);
+
+        // karts can't turn on the spot, and steer the other way in reverse
+        let speed_factor = (forward_velocity / options.full_turn_velocity).clamp(-1., 1.);
+        let target_yaw_rate = -steering * options.turn_rate * speed_factor;
+        let yaw_rate = velocity.angvel.dot(ground_normal);
+
+        let torque = (target_yaw_rate - yaw_rate) * options.steering_response;
+
+        *ef += ExternalForce {
+            torque: ground_normal * torque * mass_properties.mass,
+            ..default()
+        };
     }
 }
 
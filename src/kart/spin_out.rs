@@ -0,0 +1,119 @@
+//! Spinning out.
+//!
+//! A kart that is hit by an item spins out: it ignores its
+//! [`PlayerCommands`], loses some of its speed and spins around for a moment,
+//! sliding on loose wheels. Afterwards it is [`StatusKind::Invincible`] for a little while.
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use crate::random::Random;
+
+use super::input::{InputSystem, PlayerCommands};
+use super::status::{
+    EffectiveOptions, Modifier, Stacking, Stat, StatusEffect, StatusEffects, StatusKind,
+};
+use super::KartSystem;
+
+/// Spin out plugin.
+pub struct SpinOutPlugin;
+
+impl Plugin for SpinOutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            spin_out_karts
+                .in_set(KartSystem::Stun)
+                .after(InputSystem::Collect),
+        );
+    }
+}
+
+/// How much grip a kart keeps while spinning out.
+const SPIN_OUT_GRIP: f32 = 0.3;
+/// How fast a kart spins while spinning out, in rad/s.
+const SPIN_OUT_RATE: f32 = 9.;
+/// How much of its speed a kart keeps when it starts spinning out.
+const SPIN_OUT_SPEED_FACTOR: f32 = 0.4;
+
+/// The spin out state of a kart.
+#[derive(Clone, Component, Debug, Default)]
+pub struct SpinOut {
+    spin: f32,
+    remaining: f32,
+}
+
+impl SpinOut {
+    /// Checks if the kart is spinning out.
+    pub fn spinning(&self) -> bool {
+        self.remaining > 0.
+    }
+
+    /// Spins the kart out for `duration` seconds, either way at random.
+    ///
+    /// Returns `false` and does nothing if the kart is already spinning or is
+    /// [`StatusKind::Invincible`].
+    pub fn start(
+        &mut self,
+        status_effects: &mut StatusEffects,
+        velocity: &mut Velocity,
+        duration: f32,
+        rng: &mut Random,
+    ) -> bool {
+        if self.spinning() || status_effects.has(StatusKind::Invincible) {
+            return false;
+        }
+
+        self.spin = if rng.real() < 0.5 {
+            SPIN_OUT_RATE
+        } else {
+            -SPIN_OUT_RATE
+        };
+        self.remaining = duration;
+
+        velocity.linvel *= SPIN_OUT_SPEED_FACTOR;
+
+        status_effects.add(
+            StatusEffect::new(StatusKind::SpinOut, duration)
+                .with(Modifier::Multiply(Stat::Grip, SPIN_OUT_GRIP))
+                .stacking(Stacking::Replace),
+        );
+
+        true
+    }
+}
+
+fn spin_out_karts(
+    mut kart_query: Query<(
+        &mut SpinOut,
+        &mut PlayerCommands,
+        &mut Velocity,
+        &mut StatusEffects,
+        &GlobalTransform,
+        &EffectiveOptions,
+    )>,
+    time: Res<Time>,
+) {
+    for (mut spin_out, mut player_commands, mut velocity, mut status_effects, transform, options) in
+        kart_query.iter_mut()
+    {
+        if spin_out.spinning() {
+            player_commands.clear();
+
+            // replace the yaw of the kart with the spin
+            let up = transform.up();
+            let yaw = velocity.angvel.dot(up);
+            velocity.angvel += up * (spin_out.spin - yaw);
+
+            spin_out.remaining -= time.delta_seconds();
+
+            if !spin_out.spinning() {
+                status_effects.add(StatusEffect::new(
+                    StatusKind::Invincible,
+                    options.spin_out_invulnerability,
+                ));
+            }
+        }
+    }
+}
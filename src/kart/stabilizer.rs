@@ -0,0 +1,110 @@
+//! Arcade stabilization for karts.
+//!
+//! Keeps the kart upright by aligning it with the ground, and resists body
+//! roll by coupling the suspension of wheels on opposite sides of an axle.
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use super::wheel::{Wheel, WheelSystem};
+use super::{Chassis, KartSystem};
+
+/// Stabilizer plugin.
+pub struct StabilizerPlugin;
+
+impl Plugin for StabilizerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (apply_upright_torque, apply_anti_roll)
+                .after(KartSystem::GroundNormal)
+                .after(KartSystem::ResetForces)
+                .after(WheelSystem::Raycast),
+        );
+    }
+}
+
+/// The distance under which two wheels are considered to be on the same
+/// axle.
+const AXLE_EPSILON: f32 = 0.01;
+
+fn apply_upright_torque(
+    mut chassis_query: Query<(
+        &mut ExternalForce,
+        &GlobalTransform,
+        &Velocity,
+        &ReadMassProperties,
+        &Chassis,
+    )>,
+) {
+    for (mut ef, transform, velocity, mass_properties, chassis) in chassis_query.iter_mut() {
+        let mass_properties = mass_properties.get();
+
+        let (target, strength) = match chassis.ground_normal() {
+            Some(normal) => (normal, chassis.upright_strength),
+            None => (
+                chassis.air_up.normalize_or_zero(),
+                chassis.air_upright_strength,
+            ),
+        };
+
+        let up = transform.up();
+        let axis = up.cross(target);
+
+        // angular velocity about the up axis is steering, don't fight it
+        let angvel = velocity.angvel - up * velocity.angvel.dot(up);
+
+        let torque = axis.normalize_or_zero() * up.angle_between(target) * strength
+            - angvel * chassis.upright_damping;
+
+        *ef += ExternalForce {
+            torque: torque * mass_properties.mass,
+            ..default()
+        };
+    }
+}
+
+fn apply_anti_roll(
+    mut chassis_query: Query<(
+        &mut ExternalForce,
+        &GlobalTransform,
+        &ReadMassProperties,
+        &Chassis,
+        &Children,
+    )>,
+    wheels_query: Query<&Wheel>,
+) {
+    for (mut ef, transform, mass_properties, chassis, children) in chassis_query.iter_mut() {
+        let mass_properties = mass_properties.get();
+        let center_of_mass = transform.transform_point(mass_properties.local_center_of_mass);
+        let up = transform.up();
+
+        // find the wheel on the other side of the axle of every right wheel
+        for right in wheels_query.iter_many(children) {
+            if right.position.x <= 0. {
+                continue;
+            }
+
+            let Some(left) = wheels_query.iter_many(children).find(|left| {
+                (left.position.x + right.position.x).abs() < AXLE_EPSILON
+                    && (left.position.z - right.position.z).abs() < AXLE_EPSILON
+            }) else {
+                continue;
+            };
+
+            // the more compressed side is pushed up, the other side down
+            let force = (right.ratio() - left.ratio()) * chassis.anti_roll * mass_properties.mass;
+
+            if right.normal().is_some() {
+                let position = transform.transform_point(right.position);
+                *ef += ExternalForce::at_point(up * force, position, center_of_mass);
+            }
+
+            if left.normal().is_some() {
+                let position = transform.transform_point(left.position);
+                *ef += ExternalForce::at_point(-up * force, position, center_of_mass);
+            }
+        }
+    }
+}
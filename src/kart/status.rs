@@ -0,0 +1,340 @@
+//! Status effects.
+//!
+//! Items, surfaces and hits change how a kart drives for a while. Instead of
+//! writing to [`KartOptions`], they add a [`StatusEffect`] to the kart's
+//! [`StatusEffects`]. Every fixed step the effects are applied on top of the
+//! kart's options into [`EffectiveOptions`], which is what the physics
+//! systems read.
+
+use std::ops::Deref;
+
+use bevy::prelude::*;
+
+use crate::map::Offroad;
+
+use super::wheel::Wheel;
+use super::{KartOptions, KartSystem};
+
+/// Status effect plugin.
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.configure_sets(
+            FixedUpdate,
+            KartSystem::Status
+                .before(KartSystem::ResetForces)
+                .before(KartSystem::Stun),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                slow_offroad_karts,
+                update_status_effects.in_set(KartSystem::Status),
+            )
+                .chain(),
+        );
+    }
+}
+
+/// What kind of effect a [`StatusEffect`] is.
+///
+/// Effects of the same kind stack according to their [`Stacking`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum StatusKind {
+    /// A speed boost.
+    Boost,
+    /// Spinning out after a hit.
+    SpinOut,
+    /// Shrunk down.
+    Shrink,
+    /// Can't be spun out.
+    Invincible,
+    /// Driving offroad.
+    Offroad,
+    /// Kept close to the race by the difficulty.
+    RubberBand,
+}
+
+/// How an effect is added when the kart already has one of the same kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum Stacking {
+    /// The new effect replaces the old one.
+    Replace,
+    /// The old effect is kept, lasting as long as the longer of the two.
+    #[default]
+    Refresh,
+    /// Both effects apply, up to `max` at once. Past that, the effect closest
+    /// to running out is dropped.
+    Stack {
+        /// The most effects of this kind at once, or `0` for no limit.
+        max: usize,
+    },
+}
+
+/// A stat that status effects can change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum Stat {
+    /// [`KartOptions::max_velocity`].
+    MaxVelocity,
+    /// [`KartOptions::max_acceleration`].
+    MaxAcceleration,
+    /// [`KartOptions::turn_rate`].
+    TurnRate,
+    /// [`KartOptions::brake_deceleration`].
+    BrakeDeceleration,
+    /// [`KartOptions::rolling_resistance`].
+    RollingResistance,
+    /// [`KartOptions::bump_impulse`].
+    BumpImpulse,
+    /// The grip of every wheel.
+    Grip,
+}
+
+/// A change to a stat.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub enum Modifier {
+    /// Multiplies the stat.
+    Multiply(Stat, f32),
+    /// Adds to the stat, after every multiplier.
+    Add(Stat, f32),
+}
+
+impl Modifier {
+    /// The stat the modifier changes.
+    pub fn stat(&self) -> Stat {
+        match self {
+            Modifier::Multiply(stat, _) | Modifier::Add(stat, _) => *stat,
+        }
+    }
+}
+
+/// A temporary change to a kart.
+#[derive(Clone, Debug, Reflect)]
+pub struct StatusEffect {
+    /// The kind of the effect.
+    pub kind: StatusKind,
+    /// How the effect stacks with others of its kind.
+    pub stacking: Stacking,
+    /// The changes the effect makes to the kart's stats.
+    pub modifiers: Vec<Modifier>,
+    remaining: Option<f32>,
+}
+
+impl StatusEffect {
+    /// Creates an effect that lasts `duration` seconds.
+    pub fn new(kind: StatusKind, duration: f32) -> StatusEffect {
+        StatusEffect {
+            kind,
+            stacking: default(),
+            modifiers: Vec::new(),
+            remaining: Some(duration),
+        }
+    }
+
+    /// Creates an effect that lasts until it is removed.
+    pub fn permanent(kind: StatusKind) -> StatusEffect {
+        StatusEffect {
+            remaining: None,
+            ..StatusEffect::new(kind, 0.)
+        }
+    }
+
+    /// Adds a modifier to the effect.
+    pub fn with(mut self, modifier: Modifier) -> StatusEffect {
+        self.modifiers.push(modifier);
+        self
+    }
+
+    /// Sets how the effect stacks.
+    pub fn stacking(mut self, stacking: Stacking) -> StatusEffect {
+        self.stacking = stacking;
+        self
+    }
+
+    /// How long the effect has left, in seconds, or `None` if it lasts until
+    /// it is removed.
+    pub fn remaining(&self) -> Option<f32> {
+        self.remaining
+    }
+
+    fn expired(&self) -> bool {
+        self.remaining.is_some_and(|remaining| remaining <= 0.)
+    }
+}
+
+/// The status effects on a kart.
+#[derive(Clone, Component, Debug, Default, Reflect)]
+pub struct StatusEffects {
+    effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    /// Adds an effect, stacking it with others of its kind.
+    pub fn add(&mut self, effect: StatusEffect) {
+        match effect.stacking {
+            Stacking::Replace => {
+                self.remove(effect.kind);
+                self.effects.push(effect);
+            }
+            Stacking::Refresh => {
+                let existing = self.effects.iter_mut().find(|e| e.kind == effect.kind);
+
+                match existing {
+                    Some(existing) => {
+                        existing.remaining = match (existing.remaining, effect.remaining) {
+                            (Some(a), Some(b)) => Some(a.max(b)),
+                            _ => None,
+                        };
+                    }
+                    None => self.effects.push(effect),
+                }
+            }
+            Stacking::Stack { max } => {
+                if max > 0 && self.count(effect.kind) >= max {
+                    let shortest = self
+                        .effects
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, e)| e.kind == effect.kind)
+                        .min_by(|(_, a), (_, b)| {
+                            let a = a.remaining.unwrap_or(f32::INFINITY);
+                            let b = b.remaining.unwrap_or(f32::INFINITY);
+
+                            a.total_cmp(&b)
+                        })
+                        .map(|(i, _)| i);
+
+                    if let Some(i) = shortest {
+                        self.effects.remove(i);
+                    }
+                }
+
+                self.effects.push(effect);
+            }
+        }
+    }
+
+    /// Removes every effect of a kind.
+    pub fn remove(&mut self, kind: StatusKind) {
+        self.effects.retain(|e| e.kind != kind);
+    }
+
+    /// Checks if the kart has an effect of a kind.
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.effects.iter().any(|e| e.kind == kind)
+    }
+
+    /// The number of effects of a kind on the kart.
+    pub fn count(&self, kind: StatusKind) -> usize {
+        self.effects.iter().filter(|e| e.kind == kind).count()
+    }
+
+    /// The effects on the kart.
+    pub fn iter(&self) -> impl Iterator<Item = &StatusEffect> {
+        self.effects.iter()
+    }
+
+    /// Applies every modifier of a stat to `base`.
+    ///
+    /// Multipliers are applied first, then additions.
+    pub fn apply(&self, stat: Stat, base: f32) -> f32 {
+        let modifiers = self
+            .effects
+            .iter()
+            .flat_map(|e| e.modifiers.iter())
+            .filter(|m| m.stat() == stat);
+
+        let (multiply, add) = modifiers.fold((1., 0.), |(multiply, add), m| match m {
+            Modifier::Multiply(_, value) => (multiply * value, add),
+            Modifier::Add(_, value) => (multiply, add + value),
+        });
+
+        base * multiply + add
+    }
+
+    fn tick(&mut self, delta: f32) {
+        for effect in self.effects.iter_mut() {
+            if let Some(remaining) = &mut effect.remaining {
+                *remaining -= delta;
+            }
+        }
+
+        self.effects.retain(|e| !e.expired());
+    }
+}
+
+/// The options of a kart with its status effects applied.
+///
+/// Physics systems should read this instead of [`KartOptions`].
+#[derive(Clone, Component, Debug, Default, Reflect)]
+pub struct EffectiveOptions(KartOptions);
+
+impl Deref for EffectiveOptions {
+    type Target = KartOptions;
+
+    fn deref(&self) -> &KartOptions {
+        &self.0
+    }
+}
+
+fn update_status_effects(
+    mut kart_query: Query<(&mut StatusEffects, &mut EffectiveOptions, &KartOptions)>,
+    time: Res<Time>,
+) {
+    for (mut status_effects, mut effective_options, options) in kart_query.iter_mut() {
+        status_effects.tick(time.delta_seconds());
+
+        let mut effective = options.clone();
+
+        for (stat, value) in [
+            (Stat::MaxVelocity, &mut effective.max_velocity),
+            (Stat::MaxAcceleration, &mut effective.max_acceleration),
+            (Stat::TurnRate, &mut effective.turn_rate),
+            (Stat::BrakeDeceleration, &mut effective.brake_deceleration),
+            (Stat::RollingResistance, &mut effective.rolling_resistance),
+            (Stat::BumpImpulse, &mut effective.bump_impulse),
+        ] {
+            *value = status_effects.apply(stat, *value);
+        }
+
+        effective_options.0 = effective;
+    }
+}
+
+fn slow_offroad_karts(
+    mut kart_query: Query<(&mut StatusEffects, &KartOptions, &Children)>,
+    wheel_query: Query<&Wheel>,
+    offroad_query: Query<(), With<Offroad>>,
+) {
+    for (mut status_effects, options, children) in kart_query.iter_mut() {
+        // the wheels still hold the ground they found in the last step
+        let grounds = wheel_query
+            .iter_many(children)
+            .filter_map(|wheel| wheel.ground())
+            .collect::<Vec<_>>();
+
+        // keep the effect through jumps
+        if grounds.is_empty() {
+            continue;
+        }
+
+        let offroad = grounds
+            .iter()
+            .filter(|ground| offroad_query.contains(**ground))
+            .count();
+
+        if offroad * 2 > grounds.len() {
+            status_effects.add(
+                StatusEffect::permanent(StatusKind::Offroad)
+                    .with(Modifier::Multiply(
+                        Stat::MaxVelocity,
+                        options.offroad_velocity,
+                    ))
+                    .stacking(Stacking::Replace),
+            );
+        } else {
+            status_effects.remove(StatusKind::Offroad);
+        }
+    }
+}
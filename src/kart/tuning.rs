@@ -0,0 +1,243 @@
+//! Live kart tuning.
+//!
+//! Every [`KartDef`] can have a [`TuningProfile`], loaded from the `tuning`
+//! directory with the same name as the def: `karts/default.kart.ron` is tuned
+//! by `tuning/default.tuning.ron`. The profile is applied on top of the def to
+//! every kart using it whenever either loads or changes on disk (with the
+//! `hot_reload` feature), and each part the profile leaves out keeps the
+//! def's values. Values tweaked in the inspector during a session can be
+//! written back to the local player's profile with [`KeyCode::F2`].
+
+use bevy::asset::{io::Reader, AssetLoader, AssetPath, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::{thiserror, BoxedFuture, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use thiserror::Error;
+
+use crate::photo::photo_mode_inactive;
+
+use super::def::{KartDef, KartDefSystem};
+use super::{Chassis, KartOptions, LocalPlayer};
+
+/// The directory of tuning profiles, relative to the assets directory.
+pub const TUNING_PROFILE_DIR: &str = "tuning";
+
+/// Tuning plugin.
+pub struct TuningPlugin;
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<TuningProfile>()
+            .init_asset_loader::<TuningProfileLoader>()
+            .init_resource::<Tuning>()
+            .add_systems(
+                Update,
+                // defs are applied with commands, which have to land before
+                // the profiles go on top
+                (apply_deferred, load_tuning_profiles, apply_tuning_profiles)
+                    .chain()
+                    .after(KartDefSystem::Apply),
+            )
+            .add_systems(Update, save_tuning_profile.run_if(photo_mode_inactive));
+    }
+}
+
+/// The tuning profiles of the kart defs in use.
+#[derive(Debug, Default, Resource)]
+pub struct Tuning {
+    /// The profile of each kart def.
+    pub profiles: HashMap<AssetId<KartDef>, Handle<TuningProfile>>,
+}
+
+/// The path of the tuning profile of a kart def, relative to the assets
+/// directory.
+pub fn tuning_profile_path(def_path: &AssetPath) -> Option<String> {
+    let name = def_path.path().file_name()?.to_str()?;
+    let name = name.strip_suffix(".kart.ron")?;
+
+    Some(format!("{}/{}.tuning.ron", TUNING_PROFILE_DIR, name))
+}
+
+/// A set of tuning values that is applied to every kart with a def.
+///
+/// Each part that is `None` keeps the values of the def.
+#[derive(Asset, Clone, Debug, Default, Deserialize, Serialize, TypePath)]
+#[serde(default)]
+pub struct TuningProfile {
+    /// Replaces the movement options of the karts.
+    pub options: Option<KartOptions>,
+    /// Replaces the chassis physics properties of the karts.
+    pub chassis: Option<Chassis>,
+}
+
+/// Loads [`TuningProfile`]s from `.tuning.ron` files.
+#[derive(Default)]
+pub struct TuningProfileLoader;
+
+/// An error that can occur while loading a [`TuningProfile`].
+#[derive(Debug, Error)]
+pub enum TuningProfileLoaderError {
+    /// An IO error.
+    #[error("could not read tuning profile: {0}")]
+    Io(#[from] std::io::Error),
+    /// A RON error.
+    #[error("could not parse tuning profile: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for TuningProfileLoader {
+    type Asset = TuningProfile;
+    type Settings = ();
+    type Error = TuningProfileLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<TuningProfile, TuningProfileLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+
+            Ok(ron::de::from_bytes::<TuningProfile>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tuning.ron"]
+    }
+}
+
+fn load_tuning_profiles(
+    def_query: Query<&Handle<KartDef>, Changed<Handle<KartDef>>>,
+    mut tuning: ResMut<Tuning>,
+    asset_server: Res<AssetServer>,
+) {
+    for def in def_query.iter() {
+        if tuning.profiles.contains_key(&def.id()) {
+            continue;
+        }
+
+        let Some(path) = asset_server
+            .get_path(def.id())
+            .and_then(|path| tuning_profile_path(&path))
+        else {
+            continue;
+        };
+
+        tuning.profiles.insert(def.id(), asset_server.load(path));
+    }
+}
+
+fn apply_tuning_profiles(
+    mut profile_events: EventReader<AssetEvent<TuningProfile>>,
+    mut def_events: EventReader<AssetEvent<KartDef>>,
+    mut kart_query: Query<(&mut KartOptions, &mut Chassis, Ref<Handle<KartDef>>)>,
+    tuning: Res<Tuning>,
+    profiles: Res<Assets<TuningProfile>>,
+    defs: Res<Assets<KartDef>>,
+) {
+    let changed_profiles = profile_events
+        .read()
+        .filter_map(|ev| match ev {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    // karts that just had their definition applied need to be tuned again
+    let changed_defs = def_events
+        .read()
+        .filter_map(|ev| match ev {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    for (mut options, mut chassis, def) in kart_query.iter_mut() {
+        let Some(profile_handle) = tuning.profiles.get(&def.id()) else {
+            continue;
+        };
+
+        let (Some(profile), Some(kart_def)) =
+            (profiles.get(profile_handle.id()), defs.get(def.id()))
+        else {
+            continue;
+        };
+
+        if changed_profiles.contains(&profile_handle.id())
+            || def.is_changed()
+            || changed_defs.contains(&def.id())
+        {
+            *options = profile
+                .options
+                .as_ref()
+                .unwrap_or(&kart_def.options)
+                .clone();
+            chassis.set_tuning(profile.chassis.as_ref().unwrap_or(&kart_def.chassis));
+        }
+    }
+}
+
+fn save_tuning_profile(
+    local_kart_query: Query<(&KartOptions, &Chassis, &Handle<KartDef>), With<LocalPlayer>>,
+    asset_server: Res<AssetServer>,
+    keyboard_input: Res<Input<KeyCode>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F2) {
+        return;
+    }
+
+    let Ok((options, chassis, def)) = local_kart_query.get_single() else {
+        return;
+    };
+
+    let Some(path) = asset_server
+        .get_path(def.id())
+        .and_then(|path| tuning_profile_path(&path))
+    else {
+        warn!("the local kart has no kart def to save a tuning profile for");
+        return;
+    };
+
+    let profile = TuningProfile {
+        options: Some(options.clone()),
+        chassis: Some(chassis.clone()),
+    };
+
+    match write_tuning_profile(&path, &profile) {
+        Ok(()) => info!("saved tuning profile to {}", path),
+        Err(err) => error!("failed to save tuning profile: {}", err),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_tuning_profile(
+    path: &str,
+    profile: &TuningProfile,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use bevy::asset::io::file::FileAssetReader;
+
+    let path = FileAssetReader::get_base_path().join("assets").join(path);
+
+    let contents = ron::ser::to_string_pretty(profile, ron::ser::PrettyConfig::default())?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::write(path, contents)?;
+
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_tuning_profile(
+    _path: &str,
+    _profile: &TuningProfile,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("tuning profiles cannot be saved on the web".into())
+}
@@ -4,7 +4,11 @@ use bevy::prelude::*;
 
 use bevy_rapier3d::prelude::*;
 
-use super::KartSystem;
+use serde::{Deserialize, Serialize};
+
+use std::f32::consts::FRAC_PI_2;
+
+use super::{project_on_ground_plane, Chassis, KartSystem};
 
 /// Wheel plugin.
 pub struct WheelPlugin;
@@ -23,6 +27,13 @@ impl Plugin for WheelPlugin {
                     .in_set(WheelSystem::ApplyForce)
                     .after(KartSystem::ResetForces)
                     .after(WheelSystem::Raycast),
+            )
+            .add_systems(
+                FixedUpdate,
+                apply_wheel_grip
+                    .in_set(WheelSystem::ApplyForce)
+                    .after(KartSystem::ResetForces)
+                    .after(KartSystem::GroundNormal),
             );
     }
 }
@@ -30,7 +41,7 @@ impl Plugin for WheelPlugin {
 /// A system set for wheels.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, SystemSet)]
 pub enum WheelSystem {
-    /// Does the raycast.
+    /// Casts the wheels against the ground.
     Raycast,
     /// Applies forces.
     ApplyForce,
@@ -48,7 +59,7 @@ impl WheelBundle {
     /// Creates a new [`WheelBundle`] at a position.
     pub fn new(position: Vec3) -> WheelBundle {
         WheelBundle {
-            wheel: Wheel::new(position, 0.4),
+            wheel: Wheel::new(position, Wheel::default().max_suspension),
             ..default()
         }
     }
@@ -57,18 +68,44 @@ impl WheelBundle {
 /// A single wheel.
 ///
 /// The position will be determined by the raycast.
+///
+/// The suspension and grip are copied from the [`Chassis`] settings of the
+/// wheel's [`Axle`], unless they are set in [`Wheel::overrides`].
 #[derive(Clone, Component, Debug, Reflect)]
 pub struct Wheel {
     /// The position of the suspension, relative to the parent.
     pub position: Vec3,
+    /// The axle the wheel is on.
+    pub axle: Axle,
+    /// Settings that replace the settings of the axle for this wheel.
+    pub overrides: WheelOverrides,
     /// The max suspension length in meters.
     pub max_suspension: f32,
-    /// The force that is applied to the chassis, based on the extension ratio.
-    pub max_force: f32,
-    /// The damping force applied.
-    pub damping_factor: f32,
+    /// The length of the suspension at which the spring applies no force.
+    pub rest_length: f32,
+    /// The stiffness of the spring in N/m.
+    pub stiffness: f32,
+    /// The damping of the suspension while it compresses in N*s/m.
+    pub compression_damping: f32,
+    /// The damping of the suspension while it extends in N*s/m.
+    pub rebound_damping: f32,
+    /// How strongly the wheel resists sliding sideways, per unit of mass.
+    ///
+    /// `0` lets the wheel slide freely, which is how karts handled before
+    /// wheels had grip.
+    pub grip: f32,
+    /// The radius of the wheel.
+    ///
+    /// The wheel touches the ground this far below the end of the suspension.
+    pub radius: f32,
+    /// The width of the wheel, used by [`WheelCast::Cylinder`].
+    pub width: f32,
+    /// How the wheel finds the ground.
+    pub cast: WheelCast,
     ratio: f32,
     normal: Option<Vec3>,
+    contact_point: Option<Vec3>,
+    ground: Option<Entity>,
 }
 
 impl Wheel {
@@ -100,21 +137,140 @@ impl Wheel {
         1. - self.ratio
     }
 
+    /// The current length of the suspension.
+    pub fn length(&self) -> f32 {
+        self.max_suspension * self.ratio_minus_one()
+    }
+
     /// The normal of the suspension's contact point.
     pub fn normal(&self) -> Option<Vec3> {
         self.normal
     }
+
+    /// The point where the wheel touches the ground, in world space.
+    pub fn contact_point(&self) -> Option<Vec3> {
+        self.contact_point
+    }
+
+    /// The collider the wheel touches.
+    pub fn ground(&self) -> Option<Entity> {
+        self.ground
+    }
+}
+
+/// The axle a wheel is on.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Reflect, Serialize)]
+pub enum Axle {
+    /// The front axle.
+    #[default]
+    Front,
+    /// The rear axle.
+    Rear,
+}
+
+/// The suspension and grip settings shared by the wheels of an axle.
+///
+/// See [`Wheel`] for the meaning of each field.
+#[derive(Clone, Debug, Deserialize, Reflect, Serialize)]
+#[serde(default)]
+pub struct AxleSettings {
+    /// The max suspension length in meters.
+    pub max_suspension: f32,
+    /// The length of the suspension at which the spring applies no force.
+    pub rest_length: f32,
+    /// The stiffness of the spring in N/m.
+    pub stiffness: f32,
+    /// The damping of the suspension while it compresses in N*s/m.
+    pub compression_damping: f32,
+    /// The damping of the suspension while it extends in N*s/m.
+    pub rebound_damping: f32,
+    /// How strongly the wheels resist sliding sideways, per unit of mass.
+    pub grip: f32,
+}
+
+impl Default for AxleSettings {
+    fn default() -> AxleSettings {
+        let wheel = Wheel::default();
+
+        AxleSettings {
+            max_suspension: wheel.max_suspension,
+            rest_length: wheel.rest_length,
+            stiffness: wheel.stiffness,
+            compression_damping: wheel.compression_damping,
+            rebound_damping: wheel.rebound_damping,
+            grip: wheel.grip,
+        }
+    }
+}
+
+/// Per-wheel replacements for the [`AxleSettings`] of the wheel's axle.
+///
+/// Every field that is `None` uses the setting of the axle.
+#[derive(Clone, Debug, Default, Deserialize, Reflect, Serialize)]
+#[serde(default)]
+pub struct WheelOverrides {
+    /// Replaces [`AxleSettings::max_suspension`].
+    pub max_suspension: Option<f32>,
+    /// Replaces [`AxleSettings::rest_length`].
+    pub rest_length: Option<f32>,
+    /// Replaces [`AxleSettings::stiffness`].
+    pub stiffness: Option<f32>,
+    /// Replaces [`AxleSettings::compression_damping`].
+    pub compression_damping: Option<f32>,
+    /// Replaces [`AxleSettings::rebound_damping`].
+    pub rebound_damping: Option<f32>,
+    /// Replaces [`AxleSettings::grip`].
+    pub grip: Option<f32>,
+}
+
+impl WheelOverrides {
+    /// Applies the overrides to the settings of an axle.
+    pub fn apply(&self, axle: &AxleSettings) -> AxleSettings {
+        AxleSettings {
+            max_suspension: self.max_suspension.unwrap_or(axle.max_suspension),
+            rest_length: self.rest_length.unwrap_or(axle.rest_length),
+            stiffness: self.stiffness.unwrap_or(axle.stiffness),
+            compression_damping: self.compression_damping.unwrap_or(axle.compression_damping),
+            rebound_damping: self.rebound_damping.unwrap_or(axle.rebound_damping),
+            grip: self.grip.unwrap_or(axle.grip),
+        }
+    }
+}
+
+/// How a wheel finds the ground.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Reflect, Serialize)]
+pub enum WheelCast {
+    /// Casts a single ray.
+    ///
+    /// This is the cheapest, but the wheel can fall into seams between
+    /// triangles and catch on edges.
+    #[default]
+    Ray,
+    /// Casts a sphere the size of the wheel.
+    Sphere,
+    /// Casts a cylinder the size of the wheel, lying along the axle.
+    Cylinder,
 }
 
 impl Default for Wheel {
     fn default() -> Wheel {
         Wheel {
             position: default(),
+            axle: default(),
+            overrides: default(),
             max_suspension: 0.35,
-            max_force: 4.,
-            damping_factor: 0.2,
+            rest_length: 0.3,
+            stiffness: 600.,
+            compression_damping: 30.,
+            rebound_damping: 45.,
+            grip: 0.,
+            radius: 0.1,
+            width: 0.08,
+            cast: WheelCast::Ray,
             ratio: 0.,
             normal: None,
+            contact_point: None,
+            ground: None,
         }
     }
 }
@@ -142,22 +298,73 @@ fn apply_wheel_forces(
         let position = transform.transform_point(wheel.position);
         let center_of_mass = transform.transform_point(mass_properties.local_center_of_mass);
 
-        // calculate impulse
+        if wheel.normal.is_none() {
+            continue;
+        }
+
+        // the suspension extends as the chassis moves up
         let pointvel = velocity.linear_velocity_at_point(position, center_of_mass);
-        let damping = wheel.damping_factor * pointvel.dot(up);
+        let suspension_velocity = pointvel.dot(up);
 
-        if !wheel.extended() {
-            let force =
-                wheel.max_force * mass_properties.mass * up * (wheel.ratio - damping).max(0.);
+        let damping = if suspension_velocity < 0. {
+            wheel.compression_damping
+        } else {
+            wheel.rebound_damping
+        };
 
-            *ef += ExternalForce::at_point(force, position, center_of_mass);
-        }
+        let spring = wheel.stiffness * (wheel.rest_length - wheel.length());
+        let force = (spring - damping * suspension_velocity).max(0.);
+
+        *ef += ExternalForce::at_point(up * force, position, center_of_mass);
+    }
+}
+
+/// Pushes each grounded wheel against its sideways slide.
+///
+/// The force is proportional to the slide speed at the contact point, scaled by
+/// [`Wheel::grip`] and the wheel's share of the kart's mass, so the slide of a
+/// kart dies down over about `1 / grip` seconds. With the default grip of `0`
+/// no force is applied, and karts handle as they did before wheels had grip.
+fn apply_wheel_grip(
+    mut chassis_query: Query<(
+        &mut ExternalForce,
+        &GlobalTransform,
+        &Velocity,
+        &ReadMassProperties,
+        &Chassis,
+    )>,
+    wheel_query: Query<(&Parent, &Wheel)>,
+) {
+    for (chassis, wheel) in wheel_query.iter() {
+        let Ok((mut ef, transform, velocity, mass_properties, chassis)) =
+            chassis_query.get_mut(chassis.get())
+        else {
+            continue;
+        };
+
+        let (Some(normal), Some(contact_point)) = (wheel.normal, wheel.contact_point) else {
+            continue;
+        };
+
+        let mass_properties = mass_properties.get();
+        let center_of_mass = transform.transform_point(mass_properties.local_center_of_mass);
+
+        // each grounded wheel holds its share of the kart
+        let mass = mass_properties.mass / chassis.wheels_contacting_ground().max(1) as f32;
+
+        let right = project_on_ground_plane(normal, transform.right()).normalize_or_zero();
+        let pointvel = velocity.linear_velocity_at_point(contact_point, center_of_mass);
+        let lateral_velocity = pointvel.dot(right);
+
+        let force = -right * lateral_velocity * wheel.grip * mass;
+
+        *ef += ExternalForce::at_point(force, contact_point, center_of_mass);
     }
 }
 
 fn apply_wheel_transform(mut wheel_query: Query<(&mut Transform, &Wheel)>) {
     for (mut transform, wheel) in wheel_query.iter_mut() {
-        let position = wheel.position + -Vec3::Y * wheel.max_suspension * wheel.ratio_minus_one();
+        let position = wheel.position + -Vec3::Y * wheel.length();
 
         *transform = Transform::from_translation(position);
     }
@@ -176,20 +383,69 @@ fn do_wheel_raycast(
         // transform point into world space.
         let ray_pos = transform.transform_point(wheel.position);
         let ray_dir = transform.down();
-        let filter = QueryFilter::new().exclude_collider(chassis.get());
-
-        if let Some((_entity, ray)) = rapier_context.cast_ray_and_get_normal(
-            ray_pos,
-            ray_dir,
-            wheel.max_suspension,
-            true,
-            filter,
-        ) {
-            wheel.ratio = 1. - ray.toi / wheel.max_suspension;
-            wheel.normal = Some(ray.normal);
+        let filter = QueryFilter::new()
+            .exclude_collider(chassis.get())
+            .exclude_sensors();
+
+        let contact = match wheel.cast {
+            // the ray reaches past the suspension to the bottom of the wheel
+            WheelCast::Ray => rapier_context
+                .cast_ray_and_get_normal(
+                    ray_pos,
+                    ray_dir,
+                    wheel.max_suspension + wheel.radius,
+                    true,
+                    filter,
+                )
+                .map(|(entity, ray)| {
+                    (
+                        (ray.toi - wheel.radius).max(0.),
+                        ray.normal,
+                        ray.point,
+                        entity,
+                    )
+                }),
+            // the shape is the wheel, so it only has to travel the suspension
+            WheelCast::Sphere | WheelCast::Cylinder => {
+                let (_, rotation, _) = transform.to_scale_rotation_translation();
+
+                let (shape, rotation) = match wheel.cast {
+                    WheelCast::Cylinder => (
+                        Collider::cylinder(wheel.width / 2., wheel.radius),
+                        // lay the cylinder along the axle
+                        rotation * Quat::from_rotation_z(FRAC_PI_2),
+                    ),
+                    _ => (Collider::ball(wheel.radius), rotation),
+                };
+
+                rapier_context
+                    .cast_shape(
+                        ray_pos,
+                        rotation,
+                        ray_dir,
+                        &shape,
+                        wheel.max_suspension,
+                        true,
+                        filter,
+                    )
+                    .map(|(entity, toi)| match toi.details {
+                        Some(details) => (toi.toi, details.normal1, details.witness1, entity),
+                        // the wheel is already in the ground
+                        None => (0., transform.up(), ray_pos, entity),
+                    })
+            }
+        };
+
+        if let Some((length, normal, contact_point, ground)) = contact {
+            wheel.ratio = 1. - length / wheel.max_suspension;
+            wheel.normal = Some(normal);
+            wheel.contact_point = Some(contact_point);
+            wheel.ground = Some(ground);
         } else {
             wheel.ratio = 0.;
             wheel.normal = None;
+            wheel.contact_point = None;
+            wheel.ground = None;
         }
     }
 }
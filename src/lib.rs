@@ -1,10 +1,19 @@
 //! SOOCH CART!
 
+pub mod ai;
 pub mod camera;
 pub mod debug;
+pub mod depth_of_field;
+pub mod item;
 pub mod kart;
 pub mod map;
+pub mod photo;
+pub mod race;
+pub mod race_setup;
 pub mod random;
+pub mod replay;
+pub mod spectator;
+pub mod viewport;
 
 use bevy::app::PluginGroupBuilder;
 use bevy::prelude::*;
@@ -18,10 +27,26 @@ impl PluginGroup for GamePlugins {
             .add(camera::CameraPlugin)
             .add(map::MapPlugin)
             .add(kart::KartPlugin)
+            .add(kart::def::KartDefPlugin)
+            .add(kart::tuning::TuningPlugin)
             .add(kart::input::InputPlugin)
             .add(kart::wheel::WheelPlugin)
+            .add(kart::stabilizer::StabilizerPlugin)
+            .add(kart::air::AirPlugin)
+            .add(kart::collision::CollisionPlugin)
+            .add(kart::spin_out::SpinOutPlugin)
+            .add(kart::status::StatusPlugin)
+            .add(item::ItemPlugin)
+            .add(ai::AiPlugin)
+            .add(race::RacePlugin)
+            .add(race_setup::RaceSetupPlugin)
+            .add(replay::ReplayPlugin::default())
+            .add(spectator::SpectatorPlugin)
+            .add(viewport::ViewportPlugin)
+            .add(depth_of_field::DepthOfFieldPlugin)
+            .add(photo::PhotoPlugin)
             .add(debug::DebugPlugin)
-            .add(random::RandomPlugin)
+            .add(random::RandomPlugin::default())
             .add(GameStatePlugin)
     }
 }
@@ -43,6 +68,9 @@ pub enum GameState {
     Splash,
     /// Loading a map. See the [`map`] module high-level documentation.
     LoadingMap,
+    /// Choosing the AI karts before the race. See the [`race_setup`] module
+    /// high-level documentation.
+    RaceSetup,
     /// In a track.
     InGame,
 }
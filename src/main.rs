@@ -3,7 +3,10 @@ use bevy_rapier3d::prelude::*;
 
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
+use soochcart::ai::AiSettings;
 use soochcart::map::LoadMap;
+use soochcart::random::RandomPlugin;
+use soochcart::replay::ReplayPlugin;
 use soochcart::{GamePlugins, GameState};
 
 fn main() {
@@ -21,7 +24,17 @@ fn main() {
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
         .add_plugins(RapierDebugRenderPlugin::default())
         .add_plugins(WorldInspectorPlugin::new())
-        .add_plugins(GamePlugins)
+        .add_plugins(
+            GamePlugins
+                .set(RandomPlugin {
+                    // `--seed <n>` makes every random roll repeat between runs
+                    seed: arg("seed").and_then(|seed| seed.parse().ok()),
+                })
+                .set(ReplayPlugin {
+                    playback: arg("replay"),
+                }),
+        )
+        .insert_resource(ai_settings())
         .add_systems(Startup, setup)
         .run();
 }
@@ -39,3 +52,31 @@ fn setup(
 
     next_state.set(GameState::LoadingMap);
 }
+
+/// Fills in the race setup from the `--ai-count <n>`,
+/// `--difficulty <easy|normal|hard>` and `--no-rubber-banding` command line
+/// arguments.
+fn ai_settings() -> AiSettings {
+    let mut settings = AiSettings::default();
+
+    if let Some(count) = arg("ai-count").and_then(|count| count.parse().ok()) {
+        settings.count = count;
+    }
+
+    if let Some(difficulty) = arg("difficulty") {
+        match difficulty.parse() {
+            Ok(difficulty) => settings.difficulty = difficulty,
+            Err(err) => warn!("{}", err),
+        }
+    }
+
+    settings.rubber_banding = !std::env::args().any(|arg| arg == "--no-rubber-banding");
+    settings
+}
+
+/// Gets the value of a `--name value` command line argument.
+fn arg(name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+
+    std::env::args().skip_while(|arg| *arg != flag).nth(1)
+}
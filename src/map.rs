@@ -1,5 +1,6 @@
 //! Track loading systems.
 
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
 
@@ -17,11 +18,20 @@ impl Plugin for MapPlugin {
                 Update,
                 wait_for_assets.run_if(in_state(GameState::LoadingMap)),
             )
+            .add_systems(Update, add_map_nodes.in_set(MapSystem::Nodes))
+            .add_systems(Update, add_offroad_surfaces.after(MapSystem::Nodes))
             .add_systems(Update, add_generate_tri_meshes)
             .add_systems(Update, generate_tri_meshes);
     }
 }
 
+/// Map systems.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SystemSet)]
+pub enum MapSystem {
+    /// Adds [`MapNode`] to the named nodes of the map.
+    Nodes,
+}
+
 /// Loads a track.
 #[derive(Debug, Default, Resource)]
 pub struct LoadMap {
@@ -35,6 +45,74 @@ pub struct LoadMap {
 #[derive(Clone, Component, Debug, Default)]
 pub struct MapInstance;
 
+/// A named node of the map's glTF.
+///
+/// Maps mark things like the track path or item boxes with empty nodes whose
+/// names start with a prefix, see [`MapNodes`].
+#[derive(Clone, Component, Debug)]
+pub struct MapNode {
+    name: String,
+}
+
+impl MapNode {
+    /// The name of the node.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Finds the nodes of the map by the prefix of their names.
+///
+/// Systems using this should run after [`MapSystem::Nodes`].
+#[derive(SystemParam)]
+pub struct MapNodes<'w, 's> {
+    node_query: Query<'w, 's, (Entity, Ref<'static, MapNode>, Ref<'static, GlobalTransform>)>,
+    removed_nodes: RemovedComponents<'w, 's, MapNode>,
+}
+
+impl<'w, 's> MapNodes<'w, 's> {
+    /// The nodes named with a prefix that were added since the system last
+    /// ran.
+    pub fn added<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = Entity> + 'a {
+        self.node_query
+            .iter()
+            .filter(move |(_, node, _)| node.is_added() && node.name.starts_with(prefix))
+            .map(|(entity, _, _)| entity)
+    }
+
+    /// The positions of the nodes named with a prefix, ordered by name.
+    ///
+    /// Returns `None` if none of the nodes moved, were added or were removed
+    /// since the system last ran.
+    pub fn changed_path(&mut self, prefix: &str) -> Option<Vec<Vec3>> {
+        let mut nodes = self
+            .node_query
+            .iter()
+            .filter(|(_, node, _)| node.name.starts_with(prefix))
+            .collect::<Vec<_>>();
+
+        let changed = nodes.iter().any(|(_, _, transform)| transform.is_changed());
+        let removed = self.removed_nodes.read().count() > 0;
+
+        if !changed && !removed {
+            return None;
+        }
+
+        nodes.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+
+        Some(nodes.into_iter().map(|(_, _, t)| t.translation()).collect())
+    }
+}
+
+/// The prefix of the names of map nodes whose meshes are offroad.
+pub const OFFROAD_PREFIX: &str = "offroad";
+
+/// A part of the map that slows karts down, like grass or sand.
+///
+/// Added to the meshes of nodes named with [`OFFROAD_PREFIX`].
+#[derive(Clone, Component, Debug, Default)]
+pub struct Offroad;
+
 /// Generates collision for a mesh.
 #[derive(Clone, Component, Debug, Default)]
 pub struct GenerateTriMesh;
@@ -63,7 +141,25 @@ fn wait_for_assets(
         ..default()
     });
 
-    next_state.set(GameState::InGame);
+    next_state.set(GameState::RaceSetup);
+}
+
+fn add_map_nodes(
+    mut commands: Commands,
+    node_query: Query<(Entity, &Name), Added<Name>>,
+    parents_query: Query<&Parent>,
+    map_instance_query: Query<(), With<MapInstance>>,
+) {
+    for (entity, name) in node_query.iter() {
+        if parents_query
+            .iter_ancestors(entity)
+            .any(|parent| map_instance_query.contains(parent))
+        {
+            commands.entity(entity).insert(MapNode {
+                name: name.as_str().to_owned(),
+            });
+        }
+    }
 }
 
 fn add_generate_tri_meshes(
@@ -81,6 +177,25 @@ fn add_generate_tri_meshes(
     }
 }
 
+fn add_offroad_surfaces(
+    mut commands: Commands,
+    mesh_query: Query<Entity, Added<GenerateTriMesh>>,
+    parents_query: Query<&Parent>,
+    node_query: Query<&MapNode>,
+) {
+    for entity in mesh_query.iter() {
+        let offroad = std::iter::once(entity)
+            .chain(parents_query.iter_ancestors(entity))
+            .filter_map(|entity| node_query.get(entity).ok())
+            .any(|node| node.name.starts_with(OFFROAD_PREFIX));
+
+        if offroad {
+            commands.entity(entity).insert(Offroad);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
 fn generate_tri_meshes(
     mut commands: Commands,
     generate_query: Query<(Entity, &Handle<Mesh>), (With<GenerateTriMesh>, Without<Collider>)>,
@@ -0,0 +1,309 @@
+//! Photo mode.
+//!
+//! Pressing P pauses the game and swaps the view for a free flying camera
+//! that starts where the current view was. Inside photo mode:
+//!
+//! * WASD moves, Q and E move down and up, and holding shift moves faster.
+//! * The mouse looks around, and scrolling changes the field of view.
+//! * `-` and `=` change the exposure.
+//! * `,` and `.` close and open the aperture, blurring what is out of focus,
+//!   see [`DepthOfField`].
+//! * F focuses on whatever is in the middle of the view, and `[` and `]` move
+//!   the focus nearer and further.
+//! * C saves a PNG screenshot, or downloads it on the web.
+//!
+//! Kart inputs, AI drivers and debug hotkeys don't run while in photo mode,
+//! see [`photo_mode_inactive`].
+
+use bevy::core_pipeline::prepass::DepthPrepass;
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::render::view::screenshot::ScreenshotManager;
+use bevy::render::view::ColorGrading;
+use bevy::time::Real;
+use bevy::window::PrimaryWindow;
+
+use bevy_rapier3d::prelude::*;
+
+use crate::ai::AiSystem;
+use crate::camera::Orbit;
+use crate::depth_of_field::DepthOfField;
+use crate::kart::input::{InputSystem, PlayerCommands};
+
+/// The nearest the photo camera can focus, in meters.
+const MIN_FOCUS_DISTANCE: f32 = 0.2;
+/// The furthest the photo camera can focus, in meters.
+const MAX_FOCUS_DISTANCE: f32 = 500.;
+
+/// Photo mode plugin.
+pub struct PhotoPlugin;
+
+impl Plugin for PhotoPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PhotoMode>()
+            .configure_sets(Update, InputSystem::Collect.run_if(photo_mode_inactive))
+            .configure_sets(
+                FixedUpdate,
+                (AiSystem::Sense, AiSystem::Drive).run_if(photo_mode_inactive),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_photo_mode,
+                    fly_photo_camera,
+                    focus_photo_camera,
+                    take_photo,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// The state of photo mode.
+#[derive(Debug, Default, Resource)]
+pub struct PhotoMode {
+    camera: Option<Entity>,
+    hidden_cameras: Vec<Entity>,
+}
+
+impl PhotoMode {
+    /// Checks if the game is in photo mode.
+    pub fn active(&self) -> bool {
+        self.camera.is_some()
+    }
+}
+
+/// A run condition for systems that shouldn't run in photo mode, like debug
+/// hotkeys that would change the paused game.
+pub fn photo_mode_inactive(photo_mode: Option<Res<PhotoMode>>) -> bool {
+    !photo_mode.is_some_and(|photo_mode| photo_mode.active())
+}
+
+/// The free flying camera of photo mode.
+#[derive(Clone, Component, Debug)]
+pub struct PhotoCamera {
+    /// How fast the camera flies, in m/s.
+    pub speed: f32,
+    /// How much faster the camera flies while shift is held.
+    pub boost: f32,
+    /// How fast the camera turns, in rad per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// How much the field of view changes per scroll line, in degrees.
+    pub zoom_step: f32,
+    /// How fast the exposure changes while `-` or `=` is held, in EV/s.
+    pub exposure_rate: f32,
+    /// How fast the aperture changes while `,` or `.` is held, per second.
+    pub aperture_rate: f32,
+    /// The widest the aperture can open, see [`DepthOfField::aperture`].
+    pub max_aperture: f32,
+    /// How fast the focus distance changes while `[` or `]` is held, as a
+    /// ratio per second.
+    pub focus_rate: f32,
+}
+
+impl Default for PhotoCamera {
+    fn default() -> PhotoCamera {
+        PhotoCamera {
+            speed: 5.,
+            boost: 4.,
+            sensitivity: 0.003,
+            zoom_step: 2.,
+            exposure_rate: 1.,
+            aperture_rate: 0.01,
+            max_aperture: 0.03,
+            focus_rate: 1.,
+        }
+    }
+}
+
+fn toggle_photo_mode(
+    mut commands: Commands,
+    mut photo_mode: ResMut<PhotoMode>,
+    mut camera_query: Query<(Entity, &mut Camera, &GlobalTransform, &Projection), With<Orbit>>,
+    mut player_commands_query: Query<&mut PlayerCommands>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+    mut time: ResMut<Time<Virtual>>,
+    keyboard_input: Res<Input<KeyCode>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::P) {
+        return;
+    }
+
+    if let Some(photo_camera) = photo_mode.camera.take() {
+        // back to the game
+        commands.entity(photo_camera).despawn_recursive();
+
+        for camera in photo_mode.hidden_cameras.drain(..) {
+            if let Ok((_, mut camera, _, _)) = camera_query.get_mut(camera) {
+                camera.is_active = true;
+            }
+        }
+
+        rapier_configuration.physics_pipeline_active = true;
+        time.unpause();
+
+        return;
+    }
+
+    // start from the first view on screen
+    let view = camera_query
+        .iter()
+        .filter(|(_, camera, _, _)| camera.is_active)
+        .min_by_key(|(_, camera, _, _)| camera.order)
+        .map(|(_, _, transform, projection)| (transform.compute_transform(), projection.clone()));
+
+    let Some((transform, projection)) = view else {
+        return;
+    };
+
+    for (entity, mut camera, _, _) in camera_query.iter_mut() {
+        if camera.is_active {
+            camera.is_active = false;
+            photo_mode.hidden_cameras.push(entity);
+        }
+    }
+
+    // drop inputs from the frame photo mode started on
+    for mut player_commands in player_commands_query.iter_mut() {
+        player_commands.clear();
+    }
+
+    let camera = commands
+        .spawn((
+            Camera3dBundle {
+                transform,
+                projection,
+                ..default()
+            },
+            PhotoCamera::default(),
+            DepthOfField::default(),
+            DepthPrepass,
+        ))
+        .id();
+
+    photo_mode.camera = Some(camera);
+    rapier_configuration.physics_pipeline_active = false;
+    time.pause();
+}
+
+fn fly_photo_camera(
+    mut camera_query: Query<(
+        &mut Transform,
+        &mut Projection,
+        &mut ColorGrading,
+        &PhotoCamera,
+    )>,
+    mut motion_events: EventReader<MouseMotion>,
+    mut wheel_events: EventReader<MouseWheel>,
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time<Real>>,
+) {
+    let motion = motion_events.read().map(|ev| ev.delta).sum::<Vec2>();
+    let scroll = wheel_events
+        .read()
+        .map(|ev| match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            MouseScrollUnit::Pixel => ev.y / 16.,
+        })
+        .sum::<f32>();
+
+    let axis = |positive: KeyCode, negative: KeyCode| {
+        keyboard_input.pressed(positive) as i32 as f32
+            - keyboard_input.pressed(negative) as i32 as f32
+    };
+
+    for (mut transform, mut projection, mut color_grading, photo_camera) in camera_query.iter_mut()
+    {
+        // look around, yawing around the world up so the horizon stays level
+        let rotation = transform.rotation;
+        transform.rotation = Quat::from_axis_angle(Vec3::Y, -motion.x * photo_camera.sensitivity)
+            * rotation
+            * Quat::from_axis_angle(Vec3::X, -motion.y * photo_camera.sensitivity);
+
+        let direction = transform.forward() * axis(KeyCode::W, KeyCode::S)
+            + transform.right() * axis(KeyCode::D, KeyCode::A)
+            + Vec3::Y * axis(KeyCode::E, KeyCode::Q);
+
+        let speed = if keyboard_input.pressed(KeyCode::ShiftLeft) {
+            photo_camera.speed * photo_camera.boost
+        } else {
+            photo_camera.speed
+        };
+
+        transform.translation += direction.normalize_or_zero() * speed * time.delta_seconds();
+
+        if let Projection::Perspective(perspective) = &mut *projection {
+            let fov = perspective.fov.to_degrees() - scroll * photo_camera.zoom_step;
+
+            perspective.fov = fov.clamp(5., 120.).to_radians();
+        }
+
+        color_grading.exposure += axis(KeyCode::Equals, KeyCode::Minus)
+            * photo_camera.exposure_rate
+            * time.delta_seconds();
+    }
+}
+
+fn take_photo(
+    mut screenshot_manager: ResMut<ScreenshotManager>,
+    window_query: Query<Entity, With<PrimaryWindow>>,
+    photo_mode: Res<PhotoMode>,
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time<Real>>,
+) {
+    if !photo_mode.active() || !keyboard_input.just_pressed(KeyCode::C) {
+        return;
+    }
+
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+
+    let path = format!("photo-{}.png", time.elapsed().as_millis());
+
+    if let Err(e) = screenshot_manager.save_screenshot_to_disk(window, path) {
+        warn!("failed to take photo: {}", e);
+    }
+}
+
+fn focus_photo_camera(
+    mut camera_query: Query<(Ref<PhotoCamera>, &Transform, &mut DepthOfField)>,
+    rapier_context: Res<RapierContext>,
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time<Real>>,
+) {
+    let axis = |positive: KeyCode, negative: KeyCode| {
+        keyboard_input.pressed(positive) as i32 as f32
+            - keyboard_input.pressed(negative) as i32 as f32
+    };
+
+    for (photo_camera, transform, mut depth_of_field) in camera_query.iter_mut() {
+        // focus on what's in view when photo mode starts, and when asked to
+        if photo_camera.is_added() || keyboard_input.just_pressed(KeyCode::F) {
+            let hit = rapier_context.cast_ray(
+                transform.translation,
+                transform.forward(),
+                MAX_FOCUS_DISTANCE,
+                true,
+                QueryFilter::new().exclude_sensors(),
+            );
+
+            depth_of_field.focus_distance = hit.map_or(MAX_FOCUS_DISTANCE, |(_, toi)| toi);
+        }
+
+        let focus = depth_of_field.focus_distance
+            * (axis(KeyCode::BracketRight, KeyCode::BracketLeft)
+                * photo_camera.focus_rate
+                * time.delta_seconds())
+            .exp();
+
+        depth_of_field.focus_distance = focus.clamp(MIN_FOCUS_DISTANCE, MAX_FOCUS_DISTANCE);
+
+        let aperture = depth_of_field.aperture
+            + axis(KeyCode::Period, KeyCode::Comma)
+                * photo_camera.aperture_rate
+                * time.delta_seconds();
+
+        depth_of_field.aperture = aperture.clamp(0., photo_camera.max_aperture);
+    }
+}
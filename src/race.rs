@@ -0,0 +1,198 @@
+//! Race progress and positions.
+//!
+//! The track path is authored in the map's glTF as empty nodes named
+//! `path.000`, `path.001`, and so on. The nodes are joined in order into a
+//! closed loop, and each kart's progress is measured along it.
+
+use bevy::prelude::*;
+
+use crate::kart::Chassis;
+use crate::map::{MapNodes, MapSystem};
+
+/// Race plugin.
+pub struct RacePlugin;
+
+impl Plugin for RacePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TrackPath>()
+            .add_systems(Update, build_track_path.after(MapSystem::Nodes))
+            .add_systems(
+                Update,
+                (update_race_progress, update_race_positions)
+                    .chain()
+                    .in_set(RaceSystem::Progress)
+                    .after(build_track_path),
+            );
+    }
+}
+
+/// Race systems.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SystemSet)]
+pub enum RaceSystem {
+    /// Updates [`RaceProgress`] and [`RacePosition`].
+    Progress,
+}
+
+/// The prefix of the names of track path nodes.
+pub const TRACK_PATH_PREFIX: &str = "path.";
+
+/// The path of the track, as a closed loop.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct TrackPath {
+    points: Vec<Vec3>,
+    distances: Vec<f32>,
+    length: f32,
+}
+
+impl TrackPath {
+    /// Creates a new track path from its points, in order.
+    pub fn new(points: Vec<Vec3>) -> TrackPath {
+        let mut distances = Vec::with_capacity(points.len());
+        let mut length = 0.;
+
+        for (i, point) in points.iter().enumerate() {
+            distances.push(length);
+            length += point.distance(points[(i + 1) % points.len()]);
+        }
+
+        TrackPath {
+            points,
+            distances,
+            length,
+        }
+    }
+
+    /// Checks if the track has a path.
+    pub fn is_empty(&self) -> bool {
+        self.points.len() < 2
+    }
+
+    /// The length of one lap of the path.
+    pub fn length(&self) -> f32 {
+        self.length
+    }
+
+    /// The points of the path.
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
+    }
+
+    /// Finds the distance along the path of the point on the path closest to
+    /// `position`.
+    pub fn project(&self, position: Vec3) -> Option<f32> {
+        if self.is_empty() {
+            return None;
+        }
+
+        (0..self.points.len())
+            .map(|i| {
+                let start = self.points[i];
+                let end = self.points[(i + 1) % self.points.len()];
+                let segment = end - start;
+
+                let t = ((position - start).dot(segment)
+                    / segment.length_squared().max(f32::EPSILON))
+                .clamp(0., 1.);
+                let closest = start + segment * t;
+
+                (
+                    closest.distance_squared(position),
+                    self.distances[i] + segment.length() * t,
+                )
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, distance)| distance)
+    }
+
+    /// Gets the point at a distance along the path.
+    ///
+    /// The distance wraps around the loop.
+    pub fn point_at(&self, distance: f32) -> Option<Vec3> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let distance = distance.rem_euclid(self.length);
+        let i = self.distances.partition_point(|d| *d <= distance) - 1;
+
+        let start = self.points[i];
+        let end = self.points[(i + 1) % self.points.len()];
+        let segment_length = start.distance(end).max(f32::EPSILON);
+
+        Some(start.lerp(end, (distance - self.distances[i]) / segment_length))
+    }
+}
+
+/// How far along the track a kart is.
+#[derive(Clone, Component, Debug, Default)]
+pub struct RaceProgress {
+    lap: i32,
+    distance: Option<f32>,
+}
+
+impl RaceProgress {
+    /// The lap the kart is on, starting at `0`.
+    pub fn lap(&self) -> i32 {
+        self.lap
+    }
+
+    /// The distance of the kart along the current lap.
+    pub fn distance(&self) -> f32 {
+        self.distance.unwrap_or(0.)
+    }
+
+    /// The total distance the kart has driven along the track.
+    pub fn total(&self, path: &TrackPath) -> f32 {
+        self.lap as f32 * path.length() + self.distance()
+    }
+}
+
+/// The position of a kart in the race, starting at `1` for the leader.
+#[derive(Clone, Component, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RacePosition(pub usize);
+
+fn build_track_path(mut map_nodes: MapNodes, mut track_path: ResMut<TrackPath>) {
+    if let Some(points) = map_nodes.changed_path(TRACK_PATH_PREFIX) {
+        *track_path = TrackPath::new(points);
+    }
+}
+
+fn update_race_progress(
+    mut kart_query: Query<(&mut RaceProgress, &GlobalTransform), With<Chassis>>,
+    track_path: Res<TrackPath>,
+) {
+    for (mut progress, transform) in kart_query.iter_mut() {
+        let Some(distance) = track_path.project(transform.translation()) else {
+            continue;
+        };
+
+        // count laps as the kart crosses the start of the loop
+        if let Some(previous) = progress.distance {
+            if distance - previous < -track_path.length() / 2. {
+                progress.lap += 1;
+            } else if distance - previous > track_path.length() / 2. {
+                progress.lap -= 1;
+            }
+        }
+
+        progress.distance = Some(distance);
+    }
+}
+
+fn update_race_positions(
+    mut kart_query: Query<(Entity, &RaceProgress, &mut RacePosition)>,
+    track_path: Res<TrackPath>,
+) {
+    let mut karts = kart_query
+        .iter()
+        .map(|(kart, progress, _)| (kart, progress.total(&track_path)))
+        .collect::<Vec<_>>();
+
+    karts.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    for (place, (kart, _)) in karts.into_iter().enumerate() {
+        if let Ok((_, _, mut position)) = kart_query.get_mut(kart) {
+            position.0 = place + 1;
+        }
+    }
+}
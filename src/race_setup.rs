@@ -0,0 +1,100 @@
+//! Race setup.
+//!
+//! Once the map is loaded, a menu over a view of the starting grid lets the
+//! player choose the [`Difficulty`], rubber-banding and number of AI karts
+//! before starting the race. The menu is drawn with egui, so it works the
+//! same on the web. The `--difficulty`, `--ai-count` and `--no-rubber-banding`
+//! command line arguments fill in the menu.
+
+use bevy::prelude::*;
+
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts, EguiPlugin};
+
+use crate::ai::difficulty::Difficulty;
+use crate::ai::{AiSettings, MAX_AI_KARTS};
+use crate::kart::PLAYER_START;
+use crate::GameState;
+
+/// Race setup plugin.
+pub struct RaceSetupPlugin;
+
+impl Plugin for RaceSetupPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+
+        app.add_systems(OnEnter(GameState::RaceSetup), spawn_race_setup_camera)
+            .add_systems(OnExit(GameState::RaceSetup), despawn_race_setup_camera)
+            .add_systems(
+                Update,
+                race_setup_menu
+                    .in_set(RaceSetupSystem::Menu)
+                    .run_if(in_state(GameState::RaceSetup)),
+            );
+    }
+}
+
+/// Race setup systems.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, SystemSet)]
+pub enum RaceSetupSystem {
+    /// Shows the race setup menu, and starts the race from it.
+    Menu,
+}
+
+/// The camera looking over the starting grid during the race setup.
+#[derive(Clone, Component, Debug, Default)]
+pub struct RaceSetupCamera;
+
+fn spawn_race_setup_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera3dBundle {
+            transform: Transform::from_translation(PLAYER_START + Vec3::new(-12., 6., -10.))
+                .looking_at(PLAYER_START + Vec3::new(1., 0., 5.), Vec3::Y),
+            ..default()
+        },
+        RaceSetupCamera,
+    ));
+}
+
+fn despawn_race_setup_camera(
+    mut commands: Commands,
+    camera_query: Query<Entity, With<RaceSetupCamera>>,
+) {
+    for camera in camera_query.iter() {
+        commands.entity(camera).despawn_recursive();
+    }
+}
+
+fn race_setup_menu(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<AiSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    egui::Window::new("Race setup")
+        .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Difficulty");
+
+                for difficulty in Difficulty::ALL {
+                    ui.radio_value(
+                        &mut settings.difficulty,
+                        difficulty,
+                        format!("{:?}", difficulty),
+                    );
+                }
+            });
+
+            ui.checkbox(&mut settings.rubber_banding, "Rubber-banding");
+            ui.add(egui::Slider::new(&mut settings.count, 0..=MAX_AI_KARTS).text("AI karts"));
+
+            ui.separator();
+
+            if ui.button("Start race").clicked() {
+                next_state.set(GameState::InGame);
+            }
+        });
+}
@@ -10,11 +10,19 @@ use std::f32::consts::PI;
 /// The random plugin.
 ///
 /// Simply instantiates the [`Random`] resource.
-pub struct RandomPlugin;
+#[derive(Clone, Debug, Default)]
+pub struct RandomPlugin {
+    /// The seed of the [`Random`] resource. Without one, it is seeded from
+    /// the OS.
+    pub seed: Option<u64>,
+}
 
 impl Plugin for RandomPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Random>();
+        match self.seed {
+            Some(seed) => app.insert_resource(Random::from_seed(seed)),
+            None => app.init_resource::<Random>(),
+        };
     }
 }
 
@@ -27,6 +35,15 @@ pub struct Random {
 }
 
 impl Random {
+    /// Creates a random resource from a seed.
+    ///
+    /// Two resources with the same seed produce the same numbers.
+    pub fn from_seed(seed: u64) -> Random {
+        Random {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
     /// Gets a random number between `[0, 1)`.
     pub fn real(&mut self) -> f32 {
         self.rng.gen()
@@ -42,6 +59,30 @@ impl Random {
 
         forward * self.real() * radius
     }
+
+    /// Picks an index from a list of weights.
+    ///
+    /// Returns `None` if none of the weights are positive.
+    pub fn weighted(&mut self, weights: &[f32]) -> Option<usize> {
+        let total = weights.iter().filter(|w| **w > 0.).sum::<f32>();
+
+        if total <= 0. {
+            return None;
+        }
+
+        let mut roll = self.real() * total;
+
+        for (i, weight) in weights.iter().enumerate().filter(|(_, w)| **w > 0.) {
+            if roll < *weight {
+                return Some(i);
+            }
+
+            roll -= weight;
+        }
+
+        // floating point error, fall back to the last positive weight
+        weights.iter().rposition(|w| *w > 0.)
+    }
 }
 
 impl Default for Random {
@@ -0,0 +1,387 @@
+//! Race replays.
+//!
+//! Every race is recorded as a [`Replay`]: the [`AiSettings`] it was set up
+//! with, and where each kart was about 30 times a second. F9 saves the race so
+//! far to the `replays` directory, as `<timestamp>.replay.ron`.
+//!
+//! Starting the game with `--replay <path>`, relative to the assets directory,
+//! plays a replay back instead. The race setup is skipped for the recorded
+//! settings, and the karts follow the recorded frames instead of their
+//! drivers.
+
+use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadState};
+use bevy::prelude::*;
+use bevy::utils::{thiserror, BoxedFuture};
+
+use bevy_rapier3d::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+use thiserror::Error;
+
+use crate::ai::{AiSettings, AiSystem};
+use crate::kart::input::InputSystem;
+use crate::kart::Chassis;
+use crate::photo::photo_mode_inactive;
+use crate::race_setup::RaceSetupSystem;
+use crate::GameState;
+
+/// The directory replays are saved to, relative to the assets directory.
+pub const REPLAY_DIR: &str = "replays";
+
+/// How often the karts are recorded, in seconds.
+const FRAME_INTERVAL: f32 = 1. / 30.;
+
+/// Replay plugin.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayPlugin {
+    /// The path of a replay to play back, relative to the assets directory.
+    /// Without one, the player races as usual.
+    pub playback: Option<String>,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Replay>()
+            .init_asset_loader::<ReplayLoader>()
+            .init_resource::<ReplayRecorder>()
+            // nobody drives during playback, the karts follow the replay
+            .configure_sets(
+                Update,
+                (RaceSetupSystem::Menu, InputSystem::Collect).run_if(not_playing_back),
+            )
+            .configure_sets(
+                FixedUpdate,
+                (AiSystem::Sense, AiSystem::Drive).run_if(not_playing_back),
+            )
+            .add_systems(OnEnter(GameState::InGame), start_replay_clock)
+            .add_systems(
+                Update,
+                (
+                    (record_replay, save_replay.run_if(photo_mode_inactive))
+                        .chain()
+                        .run_if(not_playing_back),
+                    (add_playback_karts, play_replay)
+                        .chain()
+                        .run_if(resource_exists::<ReplayPlayback>()),
+                )
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                Update,
+                start_playback
+                    .run_if(resource_exists::<ReplayPlayback>())
+                    .run_if(in_state(GameState::RaceSetup)),
+            );
+
+        if let Some(path) = &self.playback {
+            app.insert_resource(ReplayPlayback::new(path.clone()))
+                .add_systems(Startup, load_replay);
+        }
+    }
+}
+
+/// A run condition for systems that shouldn't run while a replay plays back,
+/// like kart inputs.
+pub fn not_playing_back(playback: Option<Res<ReplayPlayback>>) -> bool {
+    playback.is_none()
+}
+
+/// A recorded race.
+#[derive(Asset, Clone, Debug, Default, Deserialize, Serialize, TypePath)]
+#[serde(default)]
+pub struct Replay {
+    /// The settings the race was set up with.
+    pub ai_settings: AiSettings,
+    /// The karts over time, in order.
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    /// The position and rotation of kart `index` at `time`, interpolated
+    /// between frames.
+    pub fn kart_at(&self, index: usize, time: f32) -> Option<(Vec3, Quat)> {
+        let next = self.frames.partition_point(|frame| frame.time <= time);
+
+        let (a, b) = match (next.checked_sub(1), self.frames.get(next)) {
+            (Some(prev), Some(frame)) => (&self.frames[prev], frame),
+            (Some(prev), None) => (&self.frames[prev], &self.frames[prev]),
+            (None, Some(frame)) => (frame, frame),
+            (None, None) => return None,
+        };
+
+        let (a_translation, a_rotation) = *a.karts.get(index)?;
+        let (b_translation, b_rotation) = *b.karts.get(index)?;
+
+        let t = if b.time > a.time {
+            ((time - a.time) / (b.time - a.time)).clamp(0., 1.)
+        } else {
+            0.
+        };
+
+        Some((
+            a_translation.lerp(b_translation, t),
+            a_rotation.slerp(b_rotation, t),
+        ))
+    }
+}
+
+/// The karts of a [`Replay`] at one point in time.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ReplayFrame {
+    /// The time since the race started, in seconds.
+    pub time: f32,
+    /// The position and rotation of each kart, in the order they were
+    /// spawned in.
+    pub karts: Vec<(Vec3, Quat)>,
+}
+
+/// Records the current race.
+#[derive(Debug, Default, Resource)]
+pub struct ReplayRecorder {
+    replay: Replay,
+    karts: Vec<Entity>,
+    start: f32,
+}
+
+impl ReplayRecorder {
+    /// The race recorded so far.
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+}
+
+/// Plays a replay back.
+///
+/// Exists only while a replay plays back, see [`ReplayPlugin::playback`].
+#[derive(Debug, Resource)]
+pub struct ReplayPlayback {
+    path: String,
+    replay: Handle<Replay>,
+    karts: Vec<Entity>,
+    start: f32,
+}
+
+impl ReplayPlayback {
+    /// Creates a playback of the replay at `path`, relative to the assets
+    /// directory.
+    pub fn new(path: String) -> ReplayPlayback {
+        ReplayPlayback {
+            path,
+            replay: default(),
+            karts: Vec::new(),
+            start: 0.,
+        }
+    }
+
+    /// The replay being played back.
+    pub fn replay(&self) -> &Handle<Replay> {
+        &self.replay
+    }
+}
+
+/// Loads [`Replay`]s from `.replay.ron` files.
+#[derive(Default)]
+pub struct ReplayLoader;
+
+/// An error that can occur while loading a [`Replay`].
+#[derive(Debug, Error)]
+pub enum ReplayLoaderError {
+    /// An IO error.
+    #[error("could not read replay: {0}")]
+    Io(#[from] std::io::Error),
+    /// A RON error.
+    #[error("could not parse replay: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for ReplayLoader {
+    type Asset = Replay;
+    type Settings = ();
+    type Error = ReplayLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Replay, ReplayLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+
+            Ok(ron::de::from_bytes::<Replay>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["replay.ron"]
+    }
+}
+
+/// Appends newly spawned karts to `karts`.
+///
+/// Karts spawned together are ordered by where they spawned, so they come out
+/// in the same order when the race is set up again.
+fn add_karts(karts: &mut Vec<Entity>, added: impl Iterator<Item = (Entity, Vec3)>) {
+    let mut added = added.collect::<Vec<_>>();
+    added.sort_by(|(_, a), (_, b)| {
+        a.x.total_cmp(&b.x)
+            .then(a.z.total_cmp(&b.z))
+            .then(a.y.total_cmp(&b.y))
+    });
+
+    karts.extend(added.into_iter().map(|(entity, _)| entity));
+}
+
+fn load_replay(mut playback: ResMut<ReplayPlayback>, asset_server: Res<AssetServer>) {
+    playback.replay = asset_server.load(playback.path.clone());
+}
+
+fn start_playback(
+    mut commands: Commands,
+    mut settings: ResMut<AiSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    playback: Res<ReplayPlayback>,
+    replays: Res<Assets<Replay>>,
+    asset_server: Res<AssetServer>,
+) {
+    if let Some(replay) = replays.get(playback.replay.id()) {
+        *settings = replay.ai_settings.clone();
+        next_state.set(GameState::InGame);
+    } else if asset_server.get_load_state(playback.replay.id()) == Some(LoadState::Failed) {
+        // fall back to the race setup
+        error!("failed to load replay {}", playback.path);
+        commands.remove_resource::<ReplayPlayback>();
+    }
+}
+
+fn start_replay_clock(
+    mut recorder: ResMut<ReplayRecorder>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    settings: Res<AiSettings>,
+    time: Res<Time>,
+) {
+    *recorder = ReplayRecorder {
+        replay: Replay {
+            ai_settings: settings.clone(),
+            frames: Vec::new(),
+        },
+        karts: Vec::new(),
+        start: time.elapsed_seconds(),
+    };
+
+    if let Some(mut playback) = playback {
+        playback.karts.clear();
+        playback.start = time.elapsed_seconds();
+    }
+}
+
+fn record_replay(
+    mut recorder: ResMut<ReplayRecorder>,
+    added_query: Query<(Entity, &Transform), Added<Chassis>>,
+    kart_query: Query<&Transform, With<Chassis>>,
+    time: Res<Time>,
+) {
+    add_karts(
+        &mut recorder.karts,
+        added_query.iter().map(|(e, t)| (e, t.translation)),
+    );
+
+    let time = time.elapsed_seconds() - recorder.start;
+
+    if let Some(last) = recorder.replay.frames.last() {
+        if time - last.time < FRAME_INTERVAL {
+            return;
+        }
+    }
+
+    let karts = recorder
+        .karts
+        .iter()
+        .filter_map(|kart| kart_query.get(*kart).ok())
+        .map(|transform| (transform.translation, transform.rotation))
+        .collect();
+
+    recorder.replay.frames.push(ReplayFrame { time, karts });
+}
+
+fn save_replay(recorder: Res<ReplayRecorder>, keyboard_input: Res<Input<KeyCode>>) {
+    if !keyboard_input.just_pressed(KeyCode::F9) {
+        return;
+    }
+
+    match write_replay(recorder.replay()) {
+        Ok(path) => info!("saved replay to {}", path),
+        Err(err) => error!("failed to save replay: {}", err),
+    }
+}
+
+fn add_playback_karts(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    added_query: Query<(Entity, &Transform), Added<Chassis>>,
+) {
+    for (kart, _) in added_query.iter() {
+        // moved by the replay instead of the physics
+        commands
+            .entity(kart)
+            .insert(RigidBody::KinematicPositionBased);
+    }
+
+    add_karts(
+        &mut playback.karts,
+        added_query.iter().map(|(e, t)| (e, t.translation)),
+    );
+}
+
+fn play_replay(
+    mut kart_query: Query<&mut Transform, With<Chassis>>,
+    playback: Res<ReplayPlayback>,
+    replays: Res<Assets<Replay>>,
+    time: Res<Time>,
+) {
+    let Some(replay) = replays.get(playback.replay.id()) else {
+        return;
+    };
+
+    let time = time.elapsed_seconds() - playback.start;
+
+    for (index, kart) in playback.karts.iter().enumerate() {
+        let (Ok(mut transform), Some((translation, rotation))) =
+            (kart_query.get_mut(*kart), replay.kart_at(index, time))
+        else {
+            continue;
+        };
+
+        transform.translation = translation;
+        transform.rotation = rotation;
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_replay(replay: &Replay) -> Result<String, Box<dyn std::error::Error>> {
+    use bevy::asset::io::file::FileAssetReader;
+
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = format!("{}/{}.replay.ron", REPLAY_DIR, timestamp);
+
+    let full_path = FileAssetReader::get_base_path().join("assets").join(&path);
+
+    let contents = ron::ser::to_string_pretty(replay, ron::ser::PrettyConfig::default())?;
+
+    if let Some(parent) = full_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::write(full_path, contents)?;
+
+    Ok(path)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_replay(_replay: &Replay) -> Result<String, Box<dyn std::error::Error>> {
+    Err("replays cannot be saved on the web".into())
+}
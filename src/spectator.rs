@@ -0,0 +1,281 @@
+//! Spectator camera.
+//!
+//! A [`Spectator`] camera watches one kart at a time, picked by race position
+//! or by entity. It chases the kart like a [`FollowKart`] camera, and cuts to
+//! the nearest trackside camera when one can see the kart.
+//!
+//! Trackside cameras are placed in the map's glTF as empty nodes whose names
+//! start with `trackside_camera`.
+
+use bevy::prelude::*;
+
+use bevy_rapier3d::prelude::*;
+
+use crate::camera::{CameraSystem, FollowKart, Orbit};
+use crate::kart::{Chassis, LocalPlayer};
+use crate::map::{MapNodes, MapSystem};
+use crate::photo::photo_mode_inactive;
+use crate::race::RacePosition;
+
+/// Spectator plugin.
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, add_trackside_cameras.after(MapSystem::Nodes))
+            .add_systems(
+                Update,
+                (
+                    (toggle_spectator, cycle_spectator_focus).run_if(photo_mode_inactive),
+                    direct_spectators,
+                )
+                    .chain()
+                    .before(CameraSystem::Follow),
+            );
+    }
+}
+
+/// The prefix of the names of trackside camera nodes.
+pub const TRACKSIDE_CAMERA_PREFIX: &str = "trackside_camera";
+
+/// A camera placed next to the track.
+#[derive(Clone, Component, Debug, Default)]
+pub struct TracksideCamera;
+
+/// Which kart a [`Spectator`] watches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpectatorFocus {
+    /// The kart in a race position, starting at `1` for the leader.
+    Position(usize),
+    /// A specific kart.
+    Kart(Entity),
+}
+
+impl Default for SpectatorFocus {
+    fn default() -> SpectatorFocus {
+        SpectatorFocus::Position(1)
+    }
+}
+
+/// The shot a [`Spectator`] is showing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Shot {
+    /// Chasing the kart.
+    #[default]
+    Chase,
+    /// Looking at the kart from a trackside camera.
+    Trackside(Entity),
+}
+
+/// A camera that watches the race.
+///
+/// Needs an [`Orbit`] and a [`FollowKart`] for chase shots.
+#[derive(Clone, Component, Debug)]
+pub struct Spectator {
+    /// The kart to watch.
+    pub focus: SpectatorFocus,
+    /// Whether to cut to trackside cameras.
+    pub auto_cut: bool,
+    /// How far a trackside camera can be from the kart to cut to it.
+    pub trackside_range: f32,
+    /// The shortest a shot lasts before cutting again, in seconds.
+    pub min_shot_duration: f32,
+    shot: Shot,
+    shot_duration: f32,
+    player_kart: Option<Entity>,
+}
+
+impl Spectator {
+    /// The shot the camera is showing.
+    pub fn shot(&self) -> Shot {
+        self.shot
+    }
+}
+
+impl Default for Spectator {
+    fn default() -> Spectator {
+        Spectator {
+            focus: default(),
+            auto_cut: true,
+            trackside_range: 30.,
+            min_shot_duration: 2.,
+            shot: default(),
+            shot_duration: 0.,
+            player_kart: None,
+        }
+    }
+}
+
+fn add_trackside_cameras(mut commands: Commands, map_nodes: MapNodes) {
+    for entity in map_nodes.added(TRACKSIDE_CAMERA_PREFIX) {
+        commands.entity(entity).insert(TracksideCamera);
+    }
+}
+
+/// Switches the local player's camera between following its kart and
+/// spectating, with F4.
+fn toggle_spectator(
+    mut commands: Commands,
+    mut camera_query: Query<(Entity, &mut FollowKart, &mut Orbit, Option<&Spectator>)>,
+    local_player_query: Query<(), With<LocalPlayer>>,
+    keyboard_input: Res<Input<KeyCode>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F4) {
+        return;
+    }
+
+    for (camera, mut follow_kart, mut orbit, spectator) in camera_query.iter_mut() {
+        match spectator {
+            Some(spectator) => {
+                follow_kart.kart = spectator.player_kart;
+                orbit.subject = spectator.player_kart;
+                commands.entity(camera).remove::<Spectator>();
+            }
+            None if follow_kart
+                .kart
+                .is_some_and(|kart| local_player_query.contains(kart)) =>
+            {
+                commands.entity(camera).insert(Spectator {
+                    player_kart: follow_kart.kart,
+                    ..default()
+                });
+            }
+            None => (),
+        }
+    }
+}
+
+/// Cycles the focus with the arrow keys. Tab switches between following a
+/// race position and following a kart.
+fn cycle_spectator_focus(
+    mut spectator_query: Query<&mut Spectator>,
+    kart_query: Query<(Entity, &RacePosition), With<Chassis>>,
+    keyboard_input: Res<Input<KeyCode>>,
+) {
+    let step = match (
+        keyboard_input.just_pressed(KeyCode::Right),
+        keyboard_input.just_pressed(KeyCode::Left),
+    ) {
+        (true, false) => 1,
+        (false, true) => -1,
+        _ => 0,
+    };
+    let switch = keyboard_input.just_pressed(KeyCode::Tab);
+
+    if step == 0 && !switch {
+        return;
+    }
+
+    let mut karts = kart_query.iter().collect::<Vec<_>>();
+
+    if karts.is_empty() {
+        return;
+    }
+
+    karts.sort_by_key(|(kart, _)| *kart);
+
+    for mut spectator in spectator_query.iter_mut() {
+        if switch {
+            spectator.focus = match spectator.focus {
+                SpectatorFocus::Position(position) => karts
+                    .iter()
+                    .find(|(_, p)| p.0 == position)
+                    .map(|(kart, _)| SpectatorFocus::Kart(*kart))
+                    .unwrap_or_default(),
+                SpectatorFocus::Kart(kart) => {
+                    SpectatorFocus::Position(kart_query.get(kart).map(|(_, p)| p.0).unwrap_or(1))
+                }
+            };
+        }
+
+        spectator.focus = match spectator.focus {
+            SpectatorFocus::Position(position) => {
+                let position = (position as i32 - 1 + step).rem_euclid(karts.len() as i32);
+
+                SpectatorFocus::Position(position as usize + 1)
+            }
+            SpectatorFocus::Kart(kart) => {
+                let i = karts.iter().position(|(k, _)| *k == kart).unwrap_or(0);
+                let i = (i as i32 + step).rem_euclid(karts.len() as i32);
+
+                SpectatorFocus::Kart(karts[i as usize].0)
+            }
+        };
+    }
+}
+
+fn direct_spectators(
+    mut spectator_query: Query<(&mut Spectator, &mut FollowKart, &mut Orbit, &mut Transform)>,
+    kart_query: Query<(Entity, &RacePosition, &GlobalTransform), With<Chassis>>,
+    trackside_query: Query<(Entity, &GlobalTransform), With<TracksideCamera>>,
+    rapier_context: Res<RapierContext>,
+    time: Res<Time>,
+) {
+    for (mut spectator, mut follow_kart, mut orbit, mut transform) in spectator_query.iter_mut() {
+        spectator.shot_duration += time.delta_seconds();
+
+        let kart = kart_query
+            .iter()
+            .find(|(kart, position, _)| match spectator.focus {
+                SpectatorFocus::Position(p) => position.0 == p,
+                SpectatorFocus::Kart(k) => *kart == k,
+            });
+
+        let Some((kart, _, kart_transform)) = kart else {
+            continue;
+        };
+
+        let target = kart_transform.translation();
+
+        // the nearest trackside camera that can see the kart
+        let filter = QueryFilter::exclude_dynamic().exclude_sensors();
+        let trackside = trackside_query
+            .iter()
+            .map(|(camera, t)| (camera, t.translation()))
+            .filter(|(_, position)| position.distance(target) <= spectator.trackside_range)
+            .filter(|(_, position)| {
+                let offset = target - *position;
+
+                rapier_context
+                    .cast_ray(*position, offset, 1., true, filter)
+                    .is_none()
+            })
+            .min_by(|a, b| a.1.distance(target).total_cmp(&b.1.distance(target)));
+
+        let shot = match trackside {
+            Some((camera, _)) if spectator.auto_cut => Shot::Trackside(camera),
+            _ => Shot::Chase,
+        };
+
+        // hold a shot for a while, unless it can't be held
+        let changed_kart = follow_kart.kart != Some(kart);
+        let lost_camera = matches!(
+            spectator.shot,
+            Shot::Trackside(camera) if !trackside_query.contains(camera)
+        );
+
+        if shot != spectator.shot
+            && (spectator.shot_duration >= spectator.min_shot_duration
+                || changed_kart
+                || lost_camera)
+        {
+            spectator.shot = shot;
+            spectator.shot_duration = 0.;
+        }
+
+        follow_kart.kart = Some(kart);
+
+        match spectator.shot {
+            Shot::Chase => orbit.subject = Some(kart),
+            Shot::Trackside(camera) => {
+                // the orbit would move the camera back to the kart
+                orbit.subject = None;
+
+                if let Ok((_, camera_transform)) = trackside_query.get(camera) {
+                    *transform = Transform::from_translation(camera_transform.translation())
+                        .looking_at(target, Vec3::Y);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Split-screen viewports.
+//!
+//! Every [`LocalPlayer`] kart gets its own camera, up to four. The cameras
+//! split the window between them, and are laid out again whenever the window
+//! is resized.
+
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::window::PrimaryWindow;
+
+use crate::camera::FollowKartBundle;
+use crate::kart::LocalPlayer;
+
+/// Viewport plugin.
+pub struct ViewportPlugin;
+
+impl Plugin for ViewportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ViewportLayout>()
+            .add_systems(Update, (spawn_player_cameras, layout_viewports).chain());
+    }
+}
+
+/// The most players that can share the window.
+pub const MAX_PLAYERS: usize = 4;
+
+/// How the window is split between players.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct ViewportLayout {
+    /// How the window is split with two players.
+    pub two_players: Split,
+}
+
+impl ViewportLayout {
+    /// Gets the position and size of viewport `index` out of `count`, as
+    /// fractions of the window.
+    ///
+    /// With three players, the first player gets the whole top half and the
+    /// others share the bottom half, so no quarter of the window is left empty.
+    pub fn rect(&self, index: usize, count: usize) -> Rect {
+        let (columns, rows, index) = match count {
+            0 | 1 => (1, 1, index),
+            2 if self.two_players == Split::Vertical => (2, 1, index),
+            2 => (1, 2, index),
+            3 if index == 0 => return Rect::new(0., 0., 1., 0.5),
+            3 => (2, 2, index + 1),
+            _ => (2, 2, index),
+        };
+
+        let size = Vec2::new(1. / columns as f32, 1. / rows as f32);
+        let min = Vec2::new((index % columns) as f32, (index / columns) as f32) * size;
+
+        Rect::from_corners(min, min + size)
+    }
+}
+
+/// How the window is split in two.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Split {
+    /// One viewport above the other.
+    #[default]
+    Horizontal,
+    /// One viewport next to the other.
+    Vertical,
+}
+
+/// The camera of a local player.
+#[derive(Clone, Component, Debug)]
+pub struct PlayerCamera {
+    /// The kart of the player.
+    pub kart: Entity,
+}
+
+fn spawn_player_cameras(
+    mut commands: Commands,
+    camera_query: Query<(Entity, &PlayerCamera)>,
+    player_query: Query<Entity, With<LocalPlayer>>,
+) {
+    let mut players = player_query.iter().collect::<Vec<_>>();
+    players.sort();
+    players.truncate(MAX_PLAYERS);
+
+    // remove the cameras of players that left
+    for (camera, player_camera) in camera_query.iter() {
+        if !players.contains(&player_camera.kart) {
+            commands.entity(camera).despawn_recursive();
+        }
+    }
+
+    for kart in players {
+        if camera_query.iter().any(|(_, c)| c.kart == kart) {
+            continue;
+        }
+
+        commands.spawn((
+            Camera3dBundle::default(),
+            FollowKartBundle::new(kart),
+            PlayerCamera { kart },
+        ));
+    }
+}
+
+fn layout_viewports(
+    mut camera_query: Query<(&mut Camera, &PlayerCamera)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    layout: Res<ViewportLayout>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+
+    if window_size.x == 0 || window_size.y == 0 {
+        return;
+    }
+
+    let mut cameras = camera_query.iter_mut().collect::<Vec<_>>();
+    cameras.sort_by_key(|(_, c)| c.kart);
+
+    let count = cameras.len();
+
+    for (index, (camera, _)) in cameras.iter_mut().enumerate() {
+        let viewport = (count > 1).then(|| {
+            let rect = layout.rect(index, count);
+            let position = (rect.min * window_size.as_vec2()).as_uvec2();
+            let size = (rect.size() * window_size.as_vec2())
+                .as_uvec2()
+                .max(UVec2::ONE);
+
+            Viewport {
+                physical_position: position,
+                physical_size: size,
+                ..default()
+            }
+        });
+
+        // only touch the camera when the layout changed
+        let unchanged = match (&camera.viewport, &viewport) {
+            (Some(a), Some(b)) => {
+                a.physical_position == b.physical_position && a.physical_size == b.physical_size
+            }
+            (None, None) => true,
+            _ => false,
+        };
+
+        if !unchanged {
+            camera.viewport = viewport;
+        }
+
+        if camera.order != index as isize {
+            camera.order = index as isize;
+        }
+    }
+}